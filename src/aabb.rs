@@ -0,0 +1,172 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Axis-aligned bounding box, the building block of acceleration structures
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box containing both boxes
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(&other.min), self.max.max(&other.max))
+    }
+
+    /// Grow the box by `delta` in every direction, used to give flat
+    /// primitives (i.e: axis-aligned rects) some thickness
+    pub fn padded(&self, delta: f64) -> Aabb {
+        let delta = Vec3::new(delta, delta, delta);
+        Aabb::new(self.min - delta, self.max + delta)
+    }
+
+    /// Extent of the box along the given axis (0 -> x, 1 -> y, 2 -> z)
+    pub fn axis(&self, axis: usize) -> Interval {
+        Interval::new(self.min[axis], self.max[axis])
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.max - self.min;
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Index (0 -> x, 1 -> y, 2 -> z) of the axis along which the box is the widest
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        match (extent.x, extent.y, extent.z) {
+            (x, y, z) if x >= y && x >= z => 0,
+            (_, y, z) if y >= z => 1,
+            _ => 2,
+        }
+    }
+
+    /// Slab test: intersect the ray with the 3 pairs of planes bounding the box
+    /// and check if the resulting t intervals overlap with each other.
+    pub fn hit(&self, ray: &Ray, interval: &Interval) -> bool {
+        self.clip(ray, interval).is_some()
+    }
+
+    /// Part of `interval` (in t) in which the ray is inside the box, if any
+    pub fn clip(&self, ray: &Ray, interval: &Interval) -> Option<Interval> {
+        let (mut t_min, mut t_max) = (interval.min, interval.max);
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+
+        Some(Interval::new(t_min, t_max))
+    }
+}
+
+/// Up to four boxes stored axis by axis (all the min x's, then all the min y's, ...),
+/// so a ray can be tested against all of them with a couple of SIMD instructions
+/// per slab instead of four separate tests. Used by the 4-wide BVH.
+#[derive(Clone, Copy)]
+pub struct Aabb4 {
+    min: [[f64; 4]; 3],
+    max: [[f64; 4]; 3],
+    len: usize
+}
+
+impl Aabb4 {
+    /// The boxes, in order. Panics if there are more than 4.
+    pub fn new(boxes: &[Aabb]) -> Self {
+        assert!(boxes.len() <= 4, "Aabb4 holds at most 4 boxes");
+
+        // Unused lanes get an empty box, they're masked out of the results anyway
+        let mut min = [[f64::INFINITY; 4]; 3];
+        let mut max = [[f64::NEG_INFINITY; 4]; 3];
+        for (lane, bbox) in boxes.iter().enumerate() {
+            for axis in 0..3 {
+                min[axis][lane] = bbox.min[axis];
+                max[axis][lane] = bbox.max[axis];
+            }
+        }
+
+        Self { min, max, len: boxes.len() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Which boxes the ray goes through within `interval`, as a bit per box (bit
+    /// 0 for the first), and the t at which it enters each of them
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    pub fn hit(&self, ray: &Ray, interval: &Interval) -> (u32, [f64; 4]) {
+        let (mut mask, mut entries) = (0, [f64::INFINITY; 4]);
+        for (lane, entry) in entries.iter_mut().enumerate().take(self.len) {
+            let bbox = Aabb::new(
+                Point3::new(self.min[0][lane], self.min[1][lane], self.min[2][lane]),
+                Point3::new(self.max[0][lane], self.max[1][lane], self.max[2][lane])
+            );
+            if let Some(inside) = bbox.clip(ray, interval) {
+                mask |= 1 << lane;
+                *entry = inside.min;
+            }
+        }
+
+        (mask, entries)
+    }
+
+    /// Which boxes the ray goes through within `interval`, as a bit per box (bit
+    /// 0 for the first), and the t at which it enters each of them.
+    /// SSE2 version: two registers of two f64 lanes hold the four boxes' slabs.
+    /// SSE2 is always there on x86_64.
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    pub fn hit(&self, ray: &Ray, interval: &Interval) -> (u32, [f64; 4]) {
+        use std::arch::x86_64::*;
+
+        // SAFETY: SSE2 is part of the x86_64 baseline, every x86_64 CPU has it, and
+        // every load/store reads 2 of the 4 f64s of an array at offset 0 or 2
+        unsafe {
+            let mut t_min = [_mm_set1_pd(interval.min); 2];
+            let mut t_max = [_mm_set1_pd(interval.max); 2];
+            for axis in 0..3 {
+                let inv_d = _mm_set1_pd(1.0 / ray.direction[axis]);
+                let origin = _mm_set1_pd(ray.origin[axis]);
+                for half in 0..2 {
+                    let t0 = _mm_mul_pd(_mm_sub_pd(_mm_loadu_pd(self.min[axis].as_ptr().add(2 * half)), origin), inv_d);
+                    let t1 = _mm_mul_pd(_mm_sub_pd(_mm_loadu_pd(self.max[axis].as_ptr().add(2 * half)), origin), inv_d);
+
+                    // min/max return their 2nd operand on NaN, keep the running bounds there
+                    // so degenerate slabs are ignored just like in the scalar `clip`
+                    t_min[half] = _mm_max_pd(_mm_min_pd(t0, t1), t_min[half]);
+                    t_max[half] = _mm_min_pd(_mm_max_pd(t0, t1), t_max[half]);
+                }
+            }
+
+            let low = _mm_movemask_pd(_mm_cmplt_pd(t_min[0], t_max[0]));
+            let high = _mm_movemask_pd(_mm_cmplt_pd(t_min[1], t_max[1]));
+            let mut entries = [0.0; 4];
+            _mm_storeu_pd(entries.as_mut_ptr(), t_min[0]);
+            _mm_storeu_pd(entries.as_mut_ptr().add(2), t_min[1]);
+
+            ((low | high << 2) as u32 & ((1 << self.len) - 1), entries)
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use image::GrayImage;
+
+use crate::vec3::Vec3;
+
+/// Shape of the lens opening, from a mask image: out of focus highlights (bokeh)
+/// take its shape, a heart shaped cutout gives heart shaped bokeh. Brighter pixels
+/// let more light through. Lens positions are importance sampled from the mask,
+/// so even a thin shape costs no more samples than the round default.
+pub struct Aperture {
+    width: usize,
+    height: usize,
+    /// Cumulative distribution over rows, then over the pixels of each row
+    rows_cdf: Vec<f64>,
+    cdf: Vec<f64>
+}
+
+impl Aperture {
+    /// The mask fills the lens, the longest side of the image spanning its diameter
+    pub fn from_image(mask: &GrayImage) -> Result<Self, String> {
+        let (width, height) = (mask.width() as usize, mask.height() as usize);
+
+        let mut cdf = Vec::with_capacity(width * height);
+        let mut rows_cdf = Vec::with_capacity(height);
+        let mut total = 0.0;
+        for row in mask.rows() {
+            let mut row_total = 0.0;
+            for pixel in row {
+                row_total += pixel.0[0] as f64;
+                cdf.push(row_total);
+            }
+
+            // Each row's own distribution, normalized on its own
+            let start = cdf.len() - width;
+            if row_total > 0.0 {
+                cdf[start..].iter_mut().for_each(|value| *value /= row_total);
+            }
+            total += row_total;
+            rows_cdf.push(total);
+        }
+
+        if total <= 0.0 {
+            return Err("the aperture mask is all black, no light would get through".to_string());
+        }
+        rows_cdf.iter_mut().for_each(|value| *value /= total);
+
+        Ok(Self { width, height, rows_cdf, cdf })
+    }
+
+    /// Random point on the lens, x and y in -1.0..=1.0 and proportional to the mask
+    pub fn sample(&self) -> Vec3 {
+        // First the row, then the pixel within it, by inverting their distributions
+        let (u1, u2) = (fastrand::f64(), fastrand::f64());
+        let row = self.rows_cdf.partition_point(|&value| value <= u1).min(self.height - 1);
+        let row_cdf = &self.cdf[row * self.width..(row + 1) * self.width];
+        let column = row_cdf.partition_point(|&value| value <= u2).min(self.width - 1);
+
+        // Anywhere inside the pixel, centered on the lens and y going up
+        let size = self.width.max(self.height) as f64;
+        let x = (column as f64 + fastrand::f64() - self.width as f64 / 2.0) / size * 2.0;
+        let y = (self.height as f64 / 2.0 - row as f64 - fastrand::f64()) / size * 2.0;
+        Vec3::new(x, y, 0.0)
+    }
+}
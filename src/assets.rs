@@ -0,0 +1,63 @@
+//! Finding the files a scene uses (looks rules, and whatever comes next) without
+//! baking in paths from the machine it was made on. A relative path is looked up
+//! next to the file referencing it (if any), then in the working directory, then
+//! in every search path in order.
+
+use std::path::{Path, PathBuf};
+
+/// Where relative asset paths are looked up
+#[derive(Clone, Debug, Default)]
+pub struct AssetPaths {
+    /// Folder of the file referencing the assets, before even the working directory
+    first: Option<PathBuf>,
+    /// Searched after the working directory
+    dirs: Vec<PathBuf>
+}
+
+impl AssetPaths {
+    /// Directories separated like $PATH is (':' on unix, ';' on windows)
+    pub fn new(search: &str) -> Self {
+        Self { first: None, dirs: std::env::split_paths(search).filter(|dir| !dir.as_os_str().is_empty()).collect() }
+    }
+
+    /// Search in `dir` before anywhere else, the working directory included, i.e.
+    /// the folder of the scene referencing the asset
+    pub fn with_first(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.first = Some(dir.into());
+        self
+    }
+
+    /// Resolve a file that references assets of its own (looks rules, grades),
+    /// along with the paths to find those through: next to it first
+    pub fn resolve_referencing(&self, path: &str) -> Result<(PathBuf, AssetPaths), String> {
+        let path = self.resolve(path)?;
+        let nested = match path.parent() {
+            Some(dir) => self.clone().with_first(dir),
+            None => self.clone(),
+        };
+
+        Ok((path, nested))
+    }
+
+    /// The first existing file `path` could mean, absolute ones are taken as they are
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+
+        let next_to_referencing = self.first.iter().map(|dir| dir.join(path));
+        let searched = self.dirs.iter().map(|dir| dir.join(path));
+        next_to_referencing
+            .chain(std::iter::once(path.to_path_buf()))
+            .chain(searched)
+            .find(|candidate| candidate.exists())
+            .ok_or_else(|| {
+                let tried: Vec<String> = self.first.iter().chain(&self.dirs).map(|dir| dir.display().to_string()).collect();
+                match tried.is_empty() {
+                    true => format!("'{}' not found", path.display()),
+                    false => format!("'{}' not found here or in {}", path.display(), tried.join(", ")),
+                }
+            })
+    }
+}
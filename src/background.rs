@@ -0,0 +1,176 @@
+//! What rays escaping the world see, and so the light coming from everywhere
+//! around the scene. Described on the command line like materials are:
+//!
+//! ```text
+//! solid 0,0,0
+//! gradient 1,1,1 0.5,0.7,1
+//! sky 30 120
+//! ramp 0,1,0 0:1,0.6,0.4 0.3:0.3,0.4,0.9 1:0.05,0.05,0.2
+//! ```
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::color::{self, Color};
+use crate::environment::EnvironmentMap;
+use crate::texture::{Ramp, RampInput};
+use crate::vec3::Vec3;
+
+pub trait Background: Send + Sync {
+    /// Light coming from `direction` (not necessarily unit length)
+    fn value(&self, direction: &Vec3) -> Color;
+}
+
+/// The same color all around, i.e. black for scenes lit only by their own lights
+pub struct Solid {
+    color: Color
+}
+
+impl Solid {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Background for Solid {
+    fn value(&self, _direction: &Vec3) -> Color {
+        self.color
+    }
+}
+
+/// Blend from `bottom` straight down to `top` straight up
+pub struct Gradient {
+    bottom: Color,
+    top: Color
+}
+
+impl Gradient {
+    /// The book's sky, white to light blue
+    pub const SKY: Gradient = Gradient { bottom: color::WHITE, top: Color { x: 0.5, y: 0.7, z: 1.0 } };
+
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl Background for Gradient {
+    fn value(&self, direction: &Vec3) -> Color {
+        let t = 0.5 * (direction.unit_vec().y + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> Color {
+        EnvironmentMap::value(self, direction)
+    }
+}
+
+/// A ramp up the sky: the axis is dotted with the (unit) direction, so with 0,1,0
+/// -1 is straight down and 1 straight up. A u or v input goes around the vertical
+/// axis or up from straight down, both 0..1.
+impl Background for Ramp {
+    fn value(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit_vec();
+        self.at(match self.input() {
+            RampInput::U => ((-direction.z).atan2(direction.x) + PI) / (2.0 * PI),
+            RampInput::V => (direction.y.clamp(-1.0, 1.0)).asin() / PI + 0.5,
+            RampInput::Axis(axis) => direction.dot(&axis),
+        })
+    }
+}
+
+/// Clear daytime sky lit by a sun: blue overhead, paler and warmer towards the
+/// horizon and around the sun, plus the sun's disk itself. A cheap analytic model
+/// (single Rayleigh scattering along the air mass, an exponential glow for the
+/// haze around the sun) rather than a measured one, but the sun's position alone
+/// gets plausible colors from noon to sunset.
+pub struct PhysicalSky {
+    /// Towards the sun, unit length
+    sun: Vec3,
+    /// Sunlight before the atmosphere, scales the whole sky
+    strength: f64
+}
+
+impl PhysicalSky {
+    /// Relative Rayleigh scattering of red, green and blue (∝ 1 / wavelength⁴)
+    const RAYLEIGH: Color = Color { x: 0.18, y: 0.41, z: 1.0 };
+    /// Cosine of the sun's angular radius (0.27°)
+    const SUN_COS: f64 = 0.999989;
+
+    /// Sun at `elevation` degrees above the horizon and `azimuth` degrees around the
+    /// vertical axis, 0 along -z and 90 along +x
+    pub fn new(elevation: f64, azimuth: f64) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        Self { sun, strength: 1.0 }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Air a ray going `up` (its direction's y) crosses, relative to straight up
+    fn air_mass(up: f64) -> f64 {
+        // Kasten and Young's fit, finite at the horizon
+        let zenith = up.clamp(0.0, 1.0).acos().to_degrees();
+        1.0 / (up.max(0.0) + 0.50572 * (96.07995 - zenith).powf(-1.6364))
+    }
+
+    /// What's left of light crossing `air_mass` atmospheres
+    fn transmittance(air_mass: f64) -> Color {
+        let depth = |coefficient: f64| (-0.25 * coefficient * air_mass).exp();
+        Color::new(depth(Self::RAYLEIGH.x), depth(Self::RAYLEIGH.y), depth(Self::RAYLEIGH.z))
+    }
+}
+
+impl Background for PhysicalSky {
+    fn value(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit_vec();
+        // Below the horizon: dim ground, lit by the sky above it
+        let up = direction.y.max(0.0);
+        let sunlight = self.strength * Self::transmittance(Self::air_mass(self.sun.y));
+        if direction.y < 0.0 {
+            return 0.3 * self.sun.y.max(0.05) * sunlight;
+        }
+
+        // Sunlight scattered towards us along the view ray, more the longer it is
+        let cos_sun = direction.dot(&self.sun);
+        let phase = 0.75 * (1.0 + cos_sun * cos_sun);
+        let scattered = color::WHITE - Self::transmittance(Self::air_mass(up));
+        let haze = 0.6 * (-(1.0 - cos_sun) * 12.0).exp();
+        let sky = 2.0 * (phase + haze) * scattered * sunlight;
+
+        match cos_sun > Self::SUN_COS {
+            true => sky + 2000.0 * sunlight,
+            false => sky,
+        }
+    }
+}
+
+/// A background from a short description: "solid <color>", "gradient <bottom>
+/// <top>", "sky <sun elevation> <sun azimuth> <strength>" or "ramp <input> <stops>"
+/// (see `looks::parse_ramp`), colors written as "r,g,b"
+pub fn parse(spec: &str) -> Result<Arc<dyn Background>, String> {
+    let mut words = spec.split_whitespace();
+    let kind = words.next().ok_or("missing background")?;
+    let params: Vec<&str> = words.collect();
+
+    let color = |idx: usize, default: Color| match params.get(idx) {
+        Some(value) => crate::looks::parse_color(value),
+        None => Ok(default),
+    };
+    let number = |idx: usize, default: f64| match params.get(idx) {
+        Some(value) => value.parse().map_err(|_| format!("'{}' isn't a number", value)),
+        None => Ok(default),
+    };
+
+    match kind {
+        "solid" => Ok(Arc::new(Solid::new(color(0, color::BLACK)?))),
+        "gradient" => Ok(Arc::new(Gradient::new(color(0, Gradient::SKY.bottom)?, color(1, Gradient::SKY.top)?))),
+        "sky" => Ok(Arc::new(PhysicalSky::new(number(0, 45.0)?, number(1, 0.0)?).with_strength(number(2, 1.0)?))),
+        "ramp" => Ok(Arc::new(crate::looks::parse_ramp(&params)?)),
+        _ => Err(format!("unknown background '{}', expected solid, gradient, sky or ramp", kind)),
+    }
+}
@@ -0,0 +1,72 @@
+//! Render queues: a manifest file lists renders to make one after the other in a
+//! single run, so overnight batches don't need a script calling the renderer over
+//! and over (and building the same scene every time). A job per line, written
+//! like the command line it stands for:
+//!
+//! ```text
+//! # <output> <scene> [options]
+//! wide random --spp=256 --aperture=0
+//! close random --spp=256 --focus-dist=4 --aperture=0.3
+//! glass empty --add "sphere 0,1,0 r=1 mat=glass" --background="sky 10 60"
+//! ```
+//!
+//! Double quotes keep spaces inside a word, '#' starts a comment outside them.
+
+/// One render of the queue
+pub struct Job {
+    /// Line it came from, for messages
+    pub line: usize,
+    pub output: String,
+    pub scene: String,
+    /// Options for this render only, still with the "--add 'sphere ...'" form
+    pub options: Vec<String>
+}
+
+/// Jobs from a manifest's contents, all of them checked before anything renders
+pub fn parse(text: &str) -> Result<Vec<Job>, String> {
+    let mut jobs = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let words = split_words(line).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        match words.as_slice() {
+            [] => continue,
+            [output, scene, options @ ..] if !output.starts_with("--") && !scene.starts_with("--") => jobs.push(Job {
+                line: idx + 1,
+                output: output.clone(),
+                scene: scene.clone(),
+                options: options.to_vec(),
+            }),
+            _ => return Err(format!("line {}: expected '<output> <scene> [options]'", idx + 1)),
+        }
+    }
+
+    match jobs.is_empty() {
+        true => Err("no jobs in it".to_string()),
+        false => Ok(jobs),
+    }
+}
+
+/// Words of a line, double quoted ones kept whole (without the quotes), up to a comment
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match (c, quoted) {
+            ('"', _) => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            },
+            ('#', false) => break,
+            (c, false) if c.is_whitespace() => words.extend(word.take()),
+            (c, _) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    match quoted {
+        true => Err("unclosed quote".to_string()),
+        false => {
+            words.extend(word);
+            Ok(words)
+        },
+    }
+}
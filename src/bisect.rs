@@ -0,0 +1,50 @@
+//! Test scene reduction for bug reports: shrink a scene showing a problem (NaN
+//! pixels, panics, a render taking forever, ...) down to the few objects it
+//! still shows up with, by removing objects and re-rendering (Zeller's ddmin).
+
+use crate::hit::ObjectId;
+use crate::scenes::Scene;
+
+/// Find a small set of the scene's objects for which `fails` still holds, so
+/// that removing any single one of them makes the problem go away (1-minimal).
+/// `fails` gets the reduced scene (with no accelerator built yet) and usually renders it.
+/// Returns None if the whole scene doesn't fail to begin with.
+pub fn reduce(scene: &Scene, fails: impl Fn(Scene) -> bool) -> Option<Vec<ObjectId>> {
+    let test = |ids: &[ObjectId]| {
+        let world = scene.world.filtered(|id| ids.contains(&id));
+        fails(Scene { world, camera: scene.camera.clone(), limits: scene.limits })
+    };
+
+    let mut ids: Vec<ObjectId> = scene.world.iter().map(|(id, _)| id).collect();
+    if !test(&ids) {
+        return None;
+    }
+
+    let mut chunks = 2;
+    while ids.len() >= 2 {
+        let size = ids.len().div_ceil(chunks);
+        let subsets: Vec<Vec<ObjectId>> = ids.chunks(size).map(<[ObjectId]>::to_vec).collect();
+
+        // First try keeping just one chunk, then dropping just one
+        if let Some(subset) = subsets.iter().find(|subset| test(subset)) {
+            ids = subset.clone();
+            chunks = 2;
+            continue;
+        }
+
+        let complement = |idx: usize| -> Vec<ObjectId> {
+            subsets.iter().enumerate().filter(|&(other, _)| other != idx).flat_map(|(_, ids)| ids.clone()).collect()
+        };
+        match (0..subsets.len()).map(complement).find(|complement| chunks > 2 && test(complement)) {
+            Some(complement) => {
+                ids = complement;
+                chunks = (chunks - 1).max(2);
+            },
+            // Down to single objects and none can go: done
+            None if chunks >= ids.len() => break,
+            None => chunks = (chunks * 2).min(ids.len()),
+        }
+    }
+
+    Some(ids)
+}
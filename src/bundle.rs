@@ -0,0 +1,310 @@
+//! Scene bundles: a scene and everything it needs in a single tar file, to share
+//! it or send it to render workers.
+//!
+//! A bundle holds a `scene.conf` in the config file format (see config.rs), with
+//! the scene's name as `scene = <name>` and the options that make the scene (--add,
+//! --looks, camera and render settings) as `name = value` lines, plus the files
+//! those options point to under `files/`, images the looks rules and --add's
+//! materials read and the grade file's LUT included. Loading it extracts the files to a temp directory and
+//! points the options there.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::assets::AssetPaths;
+use crate::config;
+use crate::inline;
+use crate::looks;
+use crate::error::Error;
+use crate::grade::Grade;
+
+/// Options whose value is a file to bring along
+const FILE_OPTIONS: &[&str] = &["looks", "aperture-image", "env-map", "grade-file"];
+/// Options about this run or machine rather than the scene, never bundled
+const RUN_OPTIONS: &[&str] = &["config", "monitor", "asset-path", "output-dir"];
+const SCENE_FILE: &str = "scene.conf";
+
+/// A loaded bundle, its extracted files are removed when it's dropped
+pub struct Bundle {
+    pub scene: String,
+    /// Command line style options ("--spp=64"), files pointing to the extracted copies
+    pub options: Vec<String>,
+    dir: PathBuf
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Write the named scene and its options (command line style) to a bundle at `path`,
+/// with the files they point to found through `assets`
+pub fn pack(path: &str, scene: &str, options: &[&str], assets: &AssetPaths) -> Result<(), Error> {
+    let mut conf = format!("scene = {}\n", scene);
+    let mut packed = Packed::default();
+    for option in options {
+        let (name, value) = option.trim_start_matches("--").split_once('=').unwrap_or((option.trim_start_matches("--"), "true"));
+        if RUN_OPTIONS.contains(&name) {
+            continue;
+        }
+
+        let value = match name {
+            // Image textures and LUTs are named inside these, they come along too
+            "looks" => {
+                let (looks, nested) = assets.resolve_referencing(value).map_err(Error::Scene)?;
+                let text = read(&looks)?;
+                let text = looks::map_rule_files(&text, |image| packed.file(&nested, image).map(|archived| sibling(&archived)))
+                    .map_err(|e| Error::Scene(format!("looks '{}' {}", looks.display(), e)))?;
+                packed.text(&looks, text)
+            },
+            "grade-file" => {
+                let (grade, nested) = assets.resolve_referencing(value).map_err(Error::Scene)?;
+                let text = read(&grade)?;
+                let text = Grade::map_lut_file(&text, |lut| packed.file(&nested, lut).map(|archived| sibling(&archived)))
+                    .map_err(|e| Error::Scene(format!("grade '{}' {}", grade.display(), e)))?;
+                packed.text(&grade, text)
+            },
+            "add" => inline::map_file(value, |image| packed.file(assets, image))
+                .map_err(|e| Error::Scene(format!("--add '{}': {}", value, e)))?,
+            name if FILE_OPTIONS.contains(&name) => packed.file(assets, value).map_err(Error::Scene)?,
+            _ => value.to_string(),
+        };
+        conf += &format!("{} = {}\n", name, value);
+    }
+
+    let io_error = |path: &str| {
+        let path = path.to_string();
+        move |source| Error::Io { path, source }
+    };
+
+    let mut archive = tar::Builder::new(File::create(path).map_err(io_error(path))?);
+    append_text(&mut archive, SCENE_FILE, &conf).map_err(io_error(path))?;
+
+    for (archived, contents) in packed.files {
+        match contents {
+            Contents::Text(text) => append_text(&mut archive, &archived, &text).map_err(io_error(path))?,
+            Contents::File(file) => {
+                let file = &file.display().to_string();
+                let mut source = File::open(file).map_err(io_error(file))?;
+                archive.append_file(archived, &mut source).map_err(io_error(file))?;
+            },
+        }
+    }
+    archive.finish().map_err(io_error(path))
+}
+
+fn append_text(archive: &mut tar::Builder<File>, name: &str, text: &str) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(text.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, name, text.as_bytes())
+}
+
+/// Files going into a bundle, under the names they're archived as
+#[derive(Default)]
+struct Packed {
+    files: Vec<(String, Contents)>
+}
+
+enum Contents {
+    File(PathBuf),
+    /// Rewritten to point to the other files in the bundle
+    Text(String)
+}
+
+impl Packed {
+    /// Bring along the file `path` means, returning its name in the bundle
+    fn file(&mut self, assets: &AssetPaths, path: &str) -> Result<String, String> {
+        let source = assets.resolve(path)?;
+        let already = self.files.iter().find(|(_, contents)| matches!(contents, Contents::File(file) if *file == source));
+        match already {
+            Some((archived, _)) => Ok(archived.clone()),
+            None => Ok(self.add(&source, Contents::File(source.clone()))),
+        }
+    }
+
+    /// Bring along a rewritten copy of the file at `source`
+    fn text(&mut self, source: &Path, text: String) -> String {
+        self.add(source, Contents::Text(text))
+    }
+
+    fn add(&mut self, source: &Path, contents: Contents) -> String {
+        // Numbered, so files with the same name from different folders don't clash
+        let file_name = source.file_name().map_or("file".into(), |name| name.to_string_lossy());
+        let archived = format!("files/{}-{}", self.files.len(), file_name);
+        self.files.push((archived.clone(), contents));
+        archived
+    }
+}
+
+/// How a bundled file refers to another one: they're extracted side by side
+fn sibling(archived: &str) -> String {
+    archived.trim_start_matches("files/").to_string()
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|source| Error::Io { path: path.display().to_string(), source })
+}
+
+/// Read the bundle at `path`, extracting its files to a temp directory
+pub fn unpack(path: &str) -> Result<Bundle, Error> {
+    let io_error = |source| Error::Io { path: path.to_string(), source };
+    let unexpected = |name: &Path| Error::Scene(format!("bundle '{}' has an unexpected file '{}'", path, name.display()));
+
+    let mut archive = tar::Archive::new(File::open(path).map_err(io_error)?);
+    let dir = extraction_dir().map_err(io_error)?;
+    // From here on an error drops the bundle, taking what was extracted with it
+    let mut bundle = Bundle { scene: String::new(), options: Vec::new(), dir };
+    let mut conf = None;
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let name = entry.path().map_err(io_error)?.into_owned();
+
+        // Only plain files and folders: links could point anywhere
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            return Err(unexpected(&name));
+        }
+
+        if name == Path::new(SCENE_FILE) && kind.is_file() {
+            conf = Some(std::io::read_to_string(&mut entry).map_err(io_error)?);
+        } else if name.starts_with("files") {
+            // Nothing gets written outside the directory, whatever the bundle says
+            if !entry.unpack_in(&bundle.dir).map_err(io_error)? {
+                return Err(unexpected(&name));
+            }
+        } else {
+            return Err(unexpected(&name));
+        }
+    }
+
+    let conf = conf.ok_or_else(|| Error::Scene(format!("bundle '{}' has no {}", path, SCENE_FILE)))?;
+    let options = config::from_file(&conf).map_err(|e| Error::Scene(format!("bundle '{}' {}", path, e)))?;
+
+    let mut scene = None;
+    let options = options.into_iter()
+        .filter_map(|option| match option.strip_prefix("--scene=") {
+            Some(name) => {
+                scene = Some(name.to_string());
+                None
+            },
+            None => Some(extracted(option, &bundle.dir)),
+        })
+        .collect();
+    bundle.scene = scene.ok_or_else(|| Error::Scene(format!("bundle '{}' doesn't name its scene", path)))?;
+    bundle.options = options;
+
+    Ok(bundle)
+}
+
+/// A new, empty directory to extract into. Created here rather than reused, so
+/// nobody else can have made it first (and have us write into and delete theirs).
+fn extraction_dir() -> std::io::Result<PathBuf> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    // The global rng is seeded for the scenes, this one isn't
+    let rng = fastrand::Rng::with_seed(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64));
+    loop {
+        let dir = std::env::temp_dir().join(format!("rtw-bundle-{}-{:016x}", std::process::id(), rng.u64(..)));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The option, pointing to the extracted copy if it's a file option
+fn extracted(option: String, dir: &Path) -> String {
+    let Some((name, value)) = option.trim_start_matches("--").split_once('=') else {
+        return option;
+    };
+
+    let extracted = |file: &str| PathBuf::from(dir).join(file).display().to_string();
+    match name {
+        // Its image's path was rewritten to the archived one when packing
+        "add" => match inline::map_file(value, |image| Ok(extracted(image))) {
+            Ok(line) => format!("--add={}", line),
+            Err(_) => option,
+        },
+        name if FILE_OPTIONS.contains(&name) => format!("--{}={}", name, extracted(value)),
+        _ => option,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bundle with a valid scene.conf followed by `entries` (name, kind, link target)
+    fn bundle_with(name: &str, entries: &[(&str, tar::EntryType, &str)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rtw-test-{}-{}.tar", std::process::id(), name));
+        let mut archive = tar::Builder::new(File::create(&path).unwrap());
+
+        let conf = "scene = empty\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(conf.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, SCENE_FILE, conf.as_bytes()).unwrap();
+
+        for &(name, kind, link) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_mode(0o644);
+            match kind.is_file() {
+                true => {
+                    header.set_size(4);
+                    archive.append_data(&mut header, name, &b"evil"[..]).unwrap();
+                },
+                false => {
+                    header.set_size(0);
+                    archive.append_link(&mut header, name, link).unwrap();
+                },
+            }
+        }
+        archive.finish().unwrap();
+
+        path
+    }
+
+    #[test]
+    fn rejects_links_out_of_the_bundle() {
+        let outside = std::env::temp_dir().join(format!("rtw-test-{}-outside", std::process::id()));
+        std::fs::create_dir_all(&outside).unwrap();
+
+        let symlink = bundle_with("symlink", &[
+            ("files/x", tar::EntryType::Symlink, outside.to_str().unwrap()),
+            ("files/x/evil", tar::EntryType::Regular, ""),
+        ]);
+        let hardlink = bundle_with("hardlink", &[
+            ("files/y", tar::EntryType::Link, "/etc/hostname"),
+        ]);
+
+        for path in [symlink, hardlink] {
+            assert!(unpack(path.to_str().unwrap()).is_err());
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(!outside.join("evil").exists());
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[test]
+    fn extracts_into_a_fresh_directory() {
+        let path = bundle_with("fresh", &[("files/0-ok", tar::EntryType::Regular, "")]);
+        let (first, second) = (unpack(path.to_str().unwrap()).unwrap(), unpack(path.to_str().unwrap()).unwrap());
+        assert_ne!(first.dir, second.dir);
+        assert!(first.dir.join("files/0-ok").is_file());
+
+        let dir = first.dir.clone();
+        drop(first);
+        assert!(!dir.exists());
+        std::fs::remove_file(path).unwrap();
+    }
+}
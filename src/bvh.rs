@@ -0,0 +1,427 @@
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, Aabb4};
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::stats;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Nodes with fewer objects than this build both their subtrees on the current
+/// thread, splitting such small jobs across threads costs more than it saves.
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
+
+/// Whether traversal goes through the 4-wide copy of the tree (see WideBvh)
+const WIDE: bool = cfg!(feature = "simd");
+
+/// Bounding volume hierarchy: a binary tree of boxes where every node encloses
+/// its children. Rays that miss a node's box skip everything under it, turning
+/// the linear search for the closest hit into a logarithmic one.
+pub struct BvhNode {
+    root: Node,
+    /// The tree collapsed to 4 children per node, traversed instead of the binary
+    /// one with the "simd" feature
+    wide: Option<WideBvh>
+}
+
+struct Node {
+    bbox: Aabb,
+    children: Children
+}
+
+enum Children {
+    Leaf(Arc<dyn Hit>),
+    Split(Box<Node>, Box<Node>)
+}
+
+/// How a node's objects are divided between its two children
+#[derive(Clone, Copy, Default)]
+pub enum SplitStrategy {
+    /// Half the objects on each side, split along the widest axis. Fast to build.
+    #[default]
+    Median,
+    /// Binned Surface Area Heuristic: pick the split minimizing the expected cost
+    /// of tracing a ray through the children. Slower to build, faster to traverse,
+    /// especially on scenes with unevenly sized or distributed objects.
+    Sah { bins: usize }
+}
+
+/// Configures how a BvhNode is built
+#[derive(Clone, Copy, Default)]
+pub struct BvhBuilder {
+    strategy: SplitStrategy,
+    sequential: bool
+}
+
+impl BvhBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Build everything on the calling thread. Needed when building from inside a
+    /// rayon task that others may be waiting on (see `LazyHittable`): while the
+    /// pool works on the tree the task's thread can pick up one of those others,
+    /// and wait on itself forever.
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    /// Build a tree over the objects. Panics if there are none.
+    pub fn build(&self, objects: Vec<Arc<dyn Hit>>) -> BvhNode {
+        assert!(!objects.is_empty(), "Can't build a BVH without objects");
+
+        let boxed = |object: Arc<dyn Hit>| (object.bounding_box(), object);
+        let mut objects: Vec<(Aabb, Arc<dyn Hit>)> = match self.sequential {
+            true => objects.into_iter().map(boxed).collect(),
+            false => objects.into_par_iter().map(boxed).collect(),
+        };
+
+        let root = self.build_node(&mut objects);
+        let wide = WIDE.then(|| WideBvh::collapse(&root));
+        BvhNode { root, wide }
+    }
+
+    /// Recursively split the objects along the axis where their centroids
+    /// are the most spread out, at the position chosen by the strategy.
+    fn build_node(&self, objects: &mut [(Aabb, Arc<dyn Hit>)]) -> Node {
+        if let [(bbox, object)] = objects {
+            return Node { bbox: *bbox, children: Children::Leaf(Arc::clone(object)) };
+        }
+
+        let centroids = objects.iter().fold(None, |bounds: Option<Aabb>, (bbox, _)| {
+            let centroid = Aabb::new(bbox.centroid(), bbox.centroid());
+            Some(bounds.map_or(centroid, |bounds| bounds.surrounding(&centroid)))
+        }).unwrap();
+
+        let mid = match self.strategy {
+            SplitStrategy::Median => None,
+            SplitStrategy::Sah { bins } => BvhBuilder::partition_sah(objects, &centroids, bins.max(2)),
+        };
+
+        // The median split also catches what SAH can't split (i.e: all centroids equal)
+        let mid = mid.unwrap_or_else(|| {
+            let axis = centroids.longest_axis();
+            let mid = objects.len() / 2;
+            objects.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+                a.centroid()[axis].total_cmp(&b.centroid()[axis])
+            });
+            mid
+        });
+        let (left, right) = objects.split_at_mut(mid);
+
+        // Both halves are disjoint, so big subtrees are built in parallel
+        let (left, right) = match !self.sequential && left.len() + right.len() >= PARALLEL_BUILD_THRESHOLD {
+            true => rayon::join(|| self.build_node(left), || self.build_node(right)),
+            false => (self.build_node(left), self.build_node(right)),
+        };
+        let (left, right) = (Box::new(left), Box::new(right));
+        let bbox = left.bbox.surrounding(&right.bbox);
+
+        Node { bbox, children: Children::Split(left, right) }
+    }
+
+    /// Bin the objects by centroid along every axis and evaluate the SAH cost
+    /// `area(left) * count(left) + area(right) * count(right)` at every bin boundary.
+    /// Partitions the objects around the cheapest boundary and returns where
+    /// the right side starts, or None if no boundary separates the objects.
+    fn partition_sah(objects: &mut [(Aabb, Arc<dyn Hit>)], centroids: &Aabb, bins: usize) -> Option<usize> {
+        let bin_of = |bbox: &Aabb, axis: usize| {
+            let extent = centroids.axis(axis);
+            let offset = (bbox.centroid()[axis] - extent.min) / extent.size();
+            ((offset * bins as f64) as usize).min(bins - 1)
+        };
+
+        let mut best: Option<(f64, usize, usize)> = None;    // (cost, axis, boundary)
+        for axis in 0..3 {
+            if centroids.axis(axis).size() <= 0.0 {
+                continue;
+            }
+
+            let mut bin_boxes: Vec<Option<Aabb>> = vec![None; bins];
+            let mut bin_counts = vec![0usize; bins];
+            for (bbox, _) in objects.iter() {
+                let bin = bin_of(bbox, axis);
+                bin_counts[bin] += 1;
+                bin_boxes[bin] = Some(bin_boxes[bin].map_or(*bbox, |b| b.surrounding(bbox)));
+            }
+
+            // Sweep from the right so the left sweep can read off the right side's cost
+            let mut right_costs = vec![0.0; bins];
+            let (mut right_box, mut right_count) = (None::<Aabb>, 0);
+            for bin in (1..bins).rev() {
+                right_count += bin_counts[bin];
+                if let Some(bbox) = bin_boxes[bin] {
+                    right_box = Some(right_box.map_or(bbox, |b| b.surrounding(&bbox)));
+                }
+                right_costs[bin] = right_box.map_or(0.0, |b| b.surface_area()) * right_count as f64;
+            }
+
+            let (mut left_box, mut left_count) = (None::<Aabb>, 0);
+            for boundary in 1..bins {
+                left_count += bin_counts[boundary - 1];
+                if let Some(bbox) = bin_boxes[boundary - 1] {
+                    left_box = Some(left_box.map_or(bbox, |b| b.surrounding(&bbox)));
+                }
+                if left_count == 0 || left_count == objects.len() {
+                    continue;
+                }
+
+                let cost = left_box.map_or(0.0, |b| b.surface_area()) * left_count as f64
+                    + right_costs[boundary];
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, boundary));
+                }
+            }
+        }
+
+        // In-place partition: everything binned before the boundary goes left
+        let (_, axis, boundary) = best?;
+        let mut mid = 0;
+        for idx in 0..objects.len() {
+            if bin_of(&objects[idx].0, axis) < boundary {
+                objects.swap(idx, mid);
+                mid += 1;
+            }
+        }
+
+        Some(mid)
+    }
+}
+
+impl BvhNode {
+    /// Build a tree over the objects with the default (median split) builder.
+    /// Panics if there are none.
+    pub fn new(objects: Vec<Arc<dyn Hit>>) -> Self {
+        BvhBuilder::default().build(objects)
+    }
+
+    /// Recompute every node's box bottom up from its objects' current bounds,
+    /// keeping the tree's structure. Much cheaper than a rebuild when objects
+    /// only moved a little (i.e: between frames of an animation), but traversal
+    /// gets slower the further they drift from where the tree was built.
+    pub fn refit(&mut self) -> Aabb {
+        let bbox = self.root.refit();
+        // Collapsing is a single walk over the tree, cheap next to the refit itself
+        if self.wide.is_some() {
+            self.wide = Some(WideBvh::collapse(&self.root));
+        }
+
+        bbox
+    }
+}
+
+impl Node {
+    fn refit(&mut self) -> Aabb {
+        self.bbox = match &mut self.children {
+            Children::Leaf(object) => object.bounding_box(),
+            Children::Split(left, right) => left.refit().surrounding(&right.refit()),
+        };
+
+        self.bbox
+    }
+
+    /// Closest hit under this node, for a ray already known to hit its box
+    fn hit_inside(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        match &self.children {
+            Children::Leaf(object) => {
+                stats::count_primitive();
+                object.try_hit(ray, interval)
+            },
+            Children::Split(left, right) => {
+                // Anything on the right has to be closer than the left's hit to matter
+                let left_hit = left.try_hit(ray, interval);
+                let interval = match &left_hit {
+                    Some(hit) => Interval::new(interval.min, hit.t),
+                    None => *interval,
+                };
+
+                right.try_hit(ray, &interval).or(left_hit)
+            }
+        }
+    }
+
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        stats::count_node();
+        if !self.bbox.hit(ray, interval) {
+            return None;
+        }
+
+        self.hit_inside(ray, interval)
+    }
+}
+
+/// The binary tree flattened into nodes of up to 4 children, whose boxes are
+/// tested against a ray all at once (Aabb4, with SSE2 under the "simd" feature).
+/// Half as many levels to go down, and the 4 boxes sit next to each other in
+/// memory instead of behind a pointer each.
+struct WideBvh {
+    /// The root comes first
+    nodes: Vec<WideNode>
+}
+
+struct WideNode {
+    boxes: Aabb4,
+    children: [WideChild; 4]
+}
+
+enum WideChild {
+    Empty,
+    Node(usize),
+    Leaf(Arc<dyn Hit>)
+}
+
+impl WideBvh {
+    fn collapse(root: &Node) -> Self {
+        let mut wide = WideBvh { nodes: Vec::new() };
+        wide.add(root);
+        wide
+    }
+
+    /// Add the wide node standing for `node`'s subtree (and everything under it),
+    /// returning its index
+    fn add(&mut self, node: &Node) -> usize {
+        // Open up the biggest split child (the one rays are most likely to go
+        // through) until there are 4 children or only leaves are left
+        let mut slots = match &node.children {
+            Children::Leaf(_) => vec![node],
+            Children::Split(left, right) => vec![left.as_ref(), right.as_ref()],
+        };
+        while slots.len() < 4 {
+            let biggest = slots.iter()
+                .enumerate()
+                .filter(|(_, slot)| matches!(slot.children, Children::Split(..)))
+                .max_by(|(_, a), (_, b)| a.bbox.surface_area().total_cmp(&b.bbox.surface_area()))
+                .map(|(idx, _)| idx);
+            let Some(biggest) = biggest else {
+                break;
+            };
+            let Children::Split(left, right) = &slots[biggest].children else {
+                unreachable!("only splits are opened");
+            };
+            slots[biggest] = left;
+            slots.push(right);
+        }
+
+        // Reserve our spot first so the root stays at 0
+        let index = self.nodes.len();
+        self.nodes.push(WideNode { boxes: Aabb4::new(&[]), children: [const { WideChild::Empty }; 4] });
+
+        let boxes: Vec<Aabb> = slots.iter().map(|slot| slot.bbox).collect();
+        let mut children = [const { WideChild::Empty }; 4];
+        for (child, slot) in children.iter_mut().zip(&slots) {
+            *child = match &slot.children {
+                Children::Leaf(object) => WideChild::Leaf(Arc::clone(object)),
+                Children::Split(..) => WideChild::Node(self.add(slot)),
+            };
+        }
+        self.nodes[index] = WideNode { boxes: Aabb4::new(&boxes), children };
+
+        index
+    }
+
+    /// Closest hit under the node at `index`, for a ray already known to hit its box
+    fn hit_inside(&self, index: usize, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let node = &self.nodes[index];
+        for _ in 0..node.boxes.len() {
+            stats::count_node();
+        }
+        let (mask, entries) = node.boxes.hit(ray, interval);
+
+        // Nearest box first, so later ones can be skipped once something closer was hit
+        let mut order = [0, 1, 2, 3];
+        let order = &mut order[..node.boxes.len()];
+        order.sort_unstable_by(|&a, &b| entries[a].total_cmp(&entries[b]));
+
+        let mut interval = *interval;
+        let mut closest = None;
+        for &child in order.iter() {
+            if mask & (1 << child) == 0 || entries[child] >= interval.max {
+                continue;
+            }
+
+            let hit = match &node.children[child] {
+                WideChild::Empty => None,
+                WideChild::Node(index) => self.hit_inside(*index, ray, &interval),
+                WideChild::Leaf(object) => {
+                    stats::count_primitive();
+                    object.try_hit(ray, &interval)
+                },
+            };
+            if let Some(hit) = hit {
+                interval.max = hit.t;
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+}
+
+impl Hit for BvhNode {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        stats::count_node();
+        if !self.root.bbox.hit(ray, interval) {
+            return None;
+        }
+
+        match &self.wide {
+            Some(wide) => wide.hit_inside(0, ray, interval),
+            None => self.root.hit_inside(ray, interval),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.root.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn wide_tree_finds_the_same_hits() {
+        let rng = fastrand::Rng::with_seed(1);
+        let point = || Vec3::new(rng.f64(), rng.f64(), rng.f64()) * 20.0 - Vec3::new(10.0, 10.0, 10.0);
+
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Arc<dyn Hit>> = (0..500).map(|_| {
+            let center = point();
+            Arc::new(Sphere::new(center.x, center.y, center.z, 0.3, material.clone())) as Arc<dyn Hit>
+        }).collect();
+
+        // Odd builds (SAH, a lone object) give wide nodes with fewer than 4 children
+        for (builder, count) in [(BvhBuilder::new(), 500), (BvhBuilder::new().strategy(SplitStrategy::Sah { bins: 8 }), 500), (BvhBuilder::new(), 1)] {
+            let objects = objects[..count].to_vec();
+            let bvh = builder.build(objects.clone());
+            let wide = WideBvh::collapse(&bvh.root);
+
+            for _ in 0..2000 {
+                let ray = Ray::new(point(), point() - point());
+                let interval = Interval::new(1e-4, f64::INFINITY);
+                let closest = objects.iter()
+                    .filter_map(|object| object.try_hit(&ray, &interval))
+                    .map(|hit| hit.t)
+                    .min_by(f64::total_cmp);
+
+                let binary = bvh.root.try_hit(&ray, &interval).map(|hit| hit.t);
+                let wide = match bvh.root.bbox.hit(&ray, &interval) {
+                    true => wide.hit_inside(0, &ray, &interval).map(|hit| hit.t),
+                    false => None,
+                };
+                assert_eq!(binary, closest);
+                assert_eq!(wide, closest);
+            }
+        }
+    }
+}
@@ -1,8 +1,14 @@
+use std::sync::Arc;
+
 use crate::{
+    aperture::Aperture,
+    cubemap::CubeFace,
     ray::Ray,
+    sampler,
     vec3::{Point3, Vec3}
 };
 
+#[derive(Clone)]
 pub struct Camera {
     origin: Point3,
     x_axis: Vec3,
@@ -10,8 +16,21 @@ pub struct Camera {
     lower_left_corner: Point3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
-    lens_radius: f64
+    lens_radius: f64,
+    /// Shape of the lens opening, round if None
+    aperture: Option<Arc<Aperture>>,
+    lens_sampling: LensSampling
+}
+
+/// How points on a round lens are picked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LensSampling {
+    /// Random points, by rejection like the book does. The default.
+    Random,
+    /// A pixel's samples stratified over the lens, mapped concentrically
+    Concentric,
+    /// A pixel's samples along a golden angle spiral over the lens
+    Spiral
 }
 
 impl Camera {
@@ -43,13 +62,99 @@ impl Camera {
         Self {
             origin, x_axis, y_axis,
             lower_left_corner,
-            u, v, w,
-            lens_radius
+            u, v,
+            lens_radius,
+            aperture: None,
+            lens_sampling: LensSampling::Random
+        }
+    }
+
+    /// Pinhole camera at `origin` looking through one face of a cube map, for
+    /// images of `pixels` x `pixels`: its 90° are split between the pixels
+    /// exactly, so the faces meet without gaps or overlaps. A plain camera's edge
+    /// pixels hang out of its field of view a little, as rays aim at (i + offset) /
+    /// (width - 1).
+    pub fn cube_face(origin: Point3, face: &CubeFace, pixels: usize) -> Self {
+        let span = 2.0 * (pixels as f64 - 1.0) / pixels as f64;
+        let x_axis = span * face.right;
+        let y_axis = span * face.up;
+        let lower_left_corner = origin + face.forward - face.right - face.up;
+
+        Self {
+            origin, x_axis, y_axis,
+            lower_left_corner,
+            u: face.right, v: face.up,
+            lens_radius: 0.0,
+            aperture: None,
+            lens_sampling: LensSampling::Random
         }
     }
 
+    /// Give the lens opening a custom shape, which out of focus highlights take
+    pub fn with_aperture_shape(mut self, aperture: Arc<Aperture>) -> Self {
+        self.aperture = Some(aperture);
+        self
+    }
+
+    /// Spread the samples over a round lens more evenly than random points, for
+    /// smoother bokeh at low sample counts. Custom aperture shapes sample themselves.
+    pub fn with_lens_sampling(mut self, lens_sampling: LensSampling) -> Self {
+        self.lens_sampling = lens_sampling;
+        self
+    }
+
+    /// Aperture and focus distance
+    pub fn lens(&self) -> (f64, f64) {
+        (2.0 * self.lens_radius, self.view_center().len())
+    }
+
+    /// Same camera with a different aperture and focus distance, i.e: to tune the
+    /// depth of field without touching the scene
+    pub fn with_lens(mut self, aperture: f64, focus_dist: f64) -> Self {
+        let center = self.view_center();
+        let scale = focus_dist / center.len();
+        self.x_axis = scale * self.x_axis;
+        self.y_axis = scale * self.y_axis;
+        self.lower_left_corner = self.origin + scale * center - self.x_axis/2.0 - self.y_axis/2.0;
+        self.lens_radius = aperture/2.0;
+        self
+    }
+
+    /// Diameter of the circle a point gets blurred into, as a fraction of the
+    /// image's height. 0 on the focal plane, growing away from it.
+    pub fn blur(&self, point: Point3) -> f64 {
+        let focus_dist = self.view_center().len();
+        let depth = self.depth(point);
+
+        // The lens' cone of rays through the point, measured on the focal plane
+        2.0 * self.lens_radius * (depth - focus_dist).abs() / depth / self.y_axis.len()
+    }
+
+    /// Distance from the camera to the point along the view direction, what the
+    /// focus distance is measured in
+    pub fn depth(&self, point: Point3) -> f64 {
+        let center = self.view_center();
+        (point - self.origin).dot(&center) / center.len()
+    }
+
+    /// Ray through the center of the lens, always sharp
+    pub fn pinhole_ray(&self, s: f64, t: f64) -> Ray {
+        let direction = self.lower_left_corner + s * self.x_axis + t * self.y_axis - self.origin;
+        Ray::new(self.origin, direction)
+    }
+
     pub fn gen_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::rand_in_unit_disk();
+        let lens = match (&self.aperture, self.lens_sampling) {
+            (Some(aperture), _) => aperture.sample(),
+            (None, LensSampling::Random) => Vec3::rand_in_unit_disk(),
+            (None, LensSampling::Concentric) => Vec3::concentric_in_unit_disk(sampler::lens_2d()),
+            (None, LensSampling::Spiral) => {
+                let (radius_squared, turn) = sampler::spiral_2d();
+                let (sin, cos) = (2.0 * std::f64::consts::PI * turn).sin_cos();
+                radius_squared.sqrt() * Vec3::new(cos, sin, 0.0)
+            },
+        };
+        let rd = self.lens_radius * lens;
         let off = self.u * rd.x + self.v * rd.y;
 
         let sx = s * self.x_axis;
@@ -59,4 +164,9 @@ impl Camera {
         let direction = self.lower_left_corner + sx + ty - self.origin - off;
         Ray::new(origin, direction)
     }
+
+    /// From the origin to the center of the viewport, which sits on the focal plane
+    fn view_center(&self) -> Vec3 {
+        self.lower_left_corner + self.x_axis/2.0 + self.y_axis/2.0 - self.origin
+    }
 }
@@ -1,17 +1,18 @@
+use std::ops::AddAssign;
+
 use crate::vec3::{Vec3};
-use crate::ray::Ray;
 
 use image::Rgb;
 
 pub const WHITE: Color = Color{ x: 1.0, y: 1.0, z: 1.0 };
 pub const BLACK: Color = Color{ x: 0.0, y: 0.0, z: 0.0 };
-pub const LIGHT_BLUE: Color = Color{ x: 0.5, y: 0.7, z: 1.0 };
+pub const MAGENTA: Color = Color{ x: 1.0, y: 0.0, z: 1.0 };
 
 pub type Color = Vec3;
 
 impl Color {
     /// Turn a color into an Rgb<[u8; 3]> while also applying gamma correction.
-    pub fn to_rgb(&self, samples: f64) -> Rgb<u8> {
+    pub fn to_rgb(self, samples: f64) -> Rgb<u8> {
         let r = (256.0 * (self.x / samples).sqrt().clamp(0.0, 0.999)) as u8;
         let g = (256.0 * (self.y / samples).sqrt().clamp(0.0, 0.999)) as u8;
         let b = (256.0 * (self.z / samples).sqrt().clamp(0.0, 0.999)) as u8;
@@ -20,12 +21,55 @@ impl Color {
     }
 }
 
-impl From<&Ray> for Color {
-    /// Turn a ray into a color by lerp'ing white -> blue
-    fn from(r: &Ray) -> Self {
-        let unit_direction = r.direction.unit_vec();
-        let t = 0.5 * (unit_direction.y + 1.0);
+/// False-color ramp for visualizing a value in 0.0..=1.0:
+/// black -> blue -> cyan -> green -> yellow -> red
+pub fn heat(value: f64) -> Color {
+    const RAMP: [Color; 6] = [
+        BLACK,
+        Color{ x: 0.0, y: 0.0, z: 1.0 },
+        Color{ x: 0.0, y: 1.0, z: 1.0 },
+        Color{ x: 0.0, y: 1.0, z: 0.0 },
+        Color{ x: 1.0, y: 1.0, z: 0.0 },
+        Color{ x: 1.0, y: 0.0, z: 0.0 },
+    ];
+
+    let scaled = value.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let idx = (scaled as usize).min(RAMP.len() - 2);
+    let t = scaled - idx as f64;
+
+    (1.0 - t) * RAMP[idx] + t * RAMP[idx + 1]
+}
+
+/// Running sum of color samples using Neumaier's compensated summation.
+/// With hundreds of thousands of samples per pixel a plain f64 sum starts
+/// losing the low bits of every new sample, this keeps track of the lost part.
+#[derive(Clone, Copy, Default)]
+pub struct ColorSum {
+    sum: Color,
+    compensation: Color
+}
 
-        (1.0 - t) * WHITE + t * LIGHT_BLUE
+impl ColorSum {
+    pub fn total(&self) -> Color {
+        self.sum + self.compensation
     }
-}
\ No newline at end of file
+}
+
+impl AddAssign<Color> for ColorSum {
+    fn add_assign(&mut self, sample: Color) {
+        let mut sum = [self.sum.x, self.sum.y, self.sum.z];
+        let mut compensation = [self.compensation.x, self.compensation.y, self.compensation.z];
+        for (k, value) in [sample.x, sample.y, sample.z].into_iter().enumerate() {
+            let t = sum[k] + value;
+            // Recover whatever low order bits were rounded away from the smaller operand
+            compensation[k] += match sum[k].abs() >= value.abs() {
+                true => (sum[k] - t) + value,
+                false => (value - t) + sum[k],
+            };
+            sum[k] = t;
+        }
+
+        self.sum = Color::new(sum[0], sum[1], sum[2]);
+        self.compensation = Color::new(compensation[0], compensation[1], compensation[2]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Layered settings: built-in defaults < config file < environment < command
+//! line, so a render farm can set site wide defaults without wrapping the CLI.
+//!
+//! Every layer is turned into command line style options ("--spp=64", "--bloom")
+//! listed from the highest priority layer to the lowest, so whoever reads the
+//! first "--spp=" gets the right one. A config file has `name = value` lines
+//! (`spp = 64`, `bloom = true`) and '#' comments, environment variables are
+//! `RTW_<NAME>=<value>` (`RTW_SPP=64`, `RTW_NO_STRATIFY=true`). Switches are on
+//! with true, false becomes "--name=false" so it can turn off a switch a lower
+//! layer turned on.
+
+/// Environment variable pointing to the config file (--config=<file> wins over it)
+pub const CONFIG_VAR: &str = "RTW_CONFIG";
+const ENV_PREFIX: &str = "RTW_";
+
+/// Options from a config file's contents
+pub fn from_file(text: &str) -> Result<Vec<String>, String> {
+    let mut options = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (name, value) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected '<name> = <value>'", idx + 1))?;
+        options.push(option(name.trim(), value.trim()));
+    }
+
+    Ok(options)
+}
+
+/// Options from the RTW_* environment variables among `vars`
+pub fn from_env(vars: impl Iterator<Item = (String, String)>) -> Vec<String> {
+    let mut vars: Vec<(String, String)> = vars
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != CONFIG_VAR)
+        .collect();
+    vars.sort();    // The environment's order is arbitrary
+
+    vars.iter()
+        .map(|(name, value)| {
+            let name = name[ENV_PREFIX.len()..].to_lowercase().replace('_', "-");
+            option(&name, value)
+        })
+        .collect()
+}
+
+/// Output image size. Scenes get its aspect ratio for their camera, so the two
+/// can't disagree.
+#[derive(Clone, Copy, Debug)]
+pub struct Resolution {
+    pub width: usize,
+    pub height: usize
+}
+
+impl Resolution {
+    /// 720p, the default
+    pub const DEFAULT: Resolution = Resolution { width: 1280, height: 720 };
+
+    /// A preset (720p, 1080p, 4k, square or vertical) or "<width>x<height>"
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (width, height) = match value.to_lowercase().as_str() {
+            "720p" => (1280, 720),
+            "1080p" => (1920, 1080),
+            "4k" | "2160p" => (3840, 2160),
+            "square" => (1080, 1080),
+            "vertical" => (1080, 1920),
+            custom => {
+                let size = custom.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                size.ok_or_else(|| format!("unknown resolution '{}', expected 720p, 1080p, 4k, square, vertical or <width>x<height>", value))?
+            },
+        };
+
+        Self::checked(width, height)
+    }
+
+    /// The height following from the width and aspect ratio, the one place it's derived
+    pub fn from_width(width: usize, aspect_ratio: f64) -> Result<Self, String> {
+        if !(aspect_ratio.is_finite() && aspect_ratio > 0.0) {
+            return Err(format!("bad aspect ratio {}", aspect_ratio));
+        }
+        Self::checked(width, (width as f64 / aspect_ratio).round() as usize)
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+
+    /// Rendering divides by the size minus one, so a single row or column won't do
+    fn checked(width: usize, height: usize) -> Result<Self, String> {
+        match width >= 2 && height >= 2 {
+            true => Ok(Self { width, height }),
+            false => Err(format!("{}x{} is too small, images need at least 2x2 pixels", width, height)),
+        }
+    }
+}
+
+/// "16:9" or a plain ratio like "1.5"
+pub fn parse_aspect(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => w.parse::<f64>().ok().zip(h.parse::<f64>().ok()).map(|(w, h)| w / h),
+        None => value.parse().ok(),
+    };
+    ratio.ok_or_else(|| format!("bad aspect ratio '{}', expected i.e. 16:9 or 1.5", value))
+}
+
+/// Stack the layers, highest priority first
+pub fn layered(layers: &[Vec<String>]) -> Vec<String> {
+    layers.concat()
+}
+
+fn option(name: &str, value: &str) -> String {
+    match value {
+        "true" => format!("--{}", name),
+        value => format!("--{}={}", name, value),
+    }
+}
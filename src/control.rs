@@ -0,0 +1,101 @@
+//! Steering a render while it runs: pause and resume it, cap how many threads
+//! it keeps busy, or cancel it. A workstation render can yield to interactive
+//! work for a while without being killed and restarted.
+
+use std::sync::{Condvar, Mutex};
+
+/// Shared between the renderer (see `Renderer::with_control`) and whoever
+/// steers it from another thread
+#[derive(Default)]
+pub struct RenderControl {
+    state: Mutex<State>,
+    changed: Condvar
+}
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    cancelled: bool,
+    /// 0 for as many as the thread pool has
+    max_threads: usize,
+    /// Threads currently rendering a pixel
+    busy: usize
+}
+
+impl RenderControl {
+    /// Stop starting new pixels, the ones being rendered still finish
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    /// Skip every pixel not started yet, the render returns as soon as the ones
+    /// in flight are done
+    pub fn cancel(&self) {
+        self.update(|state| state.cancelled = true);
+    }
+
+    /// Render with at most `threads` threads, 0 to use them all again
+    pub fn set_threads(&self, threads: usize) {
+        self.update(|state| state.max_threads = threads);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    /// Run one of the commands `pause`, `resume`, `cancel` or `threads <n>`,
+    /// returning whether it was understood
+    pub fn command(&self, command: &str) -> bool {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words[..] {
+            ["pause"] => self.pause(),
+            ["resume"] => self.resume(),
+            ["cancel"] => self.cancel(),
+            ["threads", threads] => match threads.parse() {
+                Ok(threads) => self.set_threads(threads),
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Wait for the go ahead to render a pixel: not paused and a thread to spare.
+    /// None if the render was cancelled, the pixel should be skipped.
+    pub(crate) fn start_pixel(&self) -> Option<PixelGuard<'_>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.cancelled {
+                return None;
+            }
+
+            let spare_thread = state.max_threads == 0 || state.busy < state.max_threads;
+            if !state.paused && spare_thread {
+                state.busy += 1;
+                return Some(PixelGuard { control: self });
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        change(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// Frees the pixel's thread when dropped, even if rendering it panicked
+pub(crate) struct PixelGuard<'a> {
+    control: &'a RenderControl
+}
+
+impl Drop for PixelGuard<'_> {
+    fn drop(&mut self) {
+        self.control.update(|state| state.busy -= 1);
+    }
+}
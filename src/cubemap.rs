@@ -0,0 +1,133 @@
+//! Cube maps: everything around a point as six square 90° views, one along each
+//! axis, for rendering environment maps out of scenes. The faces' pixels line up
+//! exactly at the edges they share, so they assemble without seams.
+
+use std::f64::consts::PI;
+
+use crate::film::Film;
+use crate::vec3::Vec3;
+
+/// One of the cube's views, seen from inside it
+pub struct CubeFace {
+    pub name: &'static str,
+    pub forward: Vec3,
+    /// Where the face's image goes right and up, so that right x up = -forward
+    /// like for every other camera
+    pub right: Vec3,
+    pub up: Vec3
+}
+
+const fn axis(x: f64, y: f64, z: f64) -> Vec3 {
+    Vec3 { x, y, z }
+}
+
+/// +X, -X, +Y, -Y, +Z, -Z. The sides are upright, the top and bottom ones are
+/// turned so they continue the -Z face.
+pub const FACES: [CubeFace; 6] = [
+    CubeFace { name: "+x", forward: axis(1.0, 0.0, 0.0), right: axis(0.0, 0.0, 1.0), up: axis(0.0, 1.0, 0.0) },
+    CubeFace { name: "-x", forward: axis(-1.0, 0.0, 0.0), right: axis(0.0, 0.0, -1.0), up: axis(0.0, 1.0, 0.0) },
+    CubeFace { name: "+y", forward: axis(0.0, 1.0, 0.0), right: axis(1.0, 0.0, 0.0), up: axis(0.0, 0.0, 1.0) },
+    CubeFace { name: "-y", forward: axis(0.0, -1.0, 0.0), right: axis(1.0, 0.0, 0.0), up: axis(0.0, 0.0, -1.0) },
+    CubeFace { name: "+z", forward: axis(0.0, 0.0, 1.0), right: axis(-1.0, 0.0, 0.0), up: axis(0.0, 1.0, 0.0) },
+    CubeFace { name: "-z", forward: axis(0.0, 0.0, -1.0), right: axis(1.0, 0.0, 0.0), up: axis(0.0, 1.0, 0.0) },
+];
+
+/// How the six faces are laid out in a single image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeLayout {
+    /// The unfolded cube, 4 x 3 faces: -X, -Z, +X and +Z left to right across the
+    /// middle, +Y above and -Y below -Z. Corners are left black.
+    Cross,
+    /// Latitude-longitude, 4 x 2 faces worth of pixels, what `EnvironmentMap` reads
+    Equirect
+}
+
+impl CubeLayout {
+    /// Cells of the cross holding each face, in `FACES`' order
+    const CROSS: [(usize, usize); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (3, 1), (1, 1)];
+
+    /// Size of the image faces of `face` x `face` pixels assemble into
+    pub fn size(self, face: usize) -> (usize, usize) {
+        match self {
+            CubeLayout::Cross => (4 * face, 3 * face),
+            CubeLayout::Equirect => (4 * face, 2 * face),
+        }
+    }
+
+    /// One image out of the six faces, given in `FACES`' order. Equirectangular
+    /// pixels blend 2 x 2 bilinear lookups, as they don't line up with the faces'.
+    pub fn assemble(self, faces: &[&Film]) -> Film {
+        let face = faces[0].width();
+        let (width, height) = self.size(face);
+        let mut film = Film::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                *film.pixel_mut(x, y) = match self {
+                    CubeLayout::Cross => match self.nearest((x, y), face) {
+                        Some((idx, fx, fy)) => faces[idx].pixel(fx, fy),
+                        None => continue,
+                    },
+                    CubeLayout::Equirect => {
+                        let sum = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+                            .into_iter()
+                            .map(|(dx, dy)| {
+                                let (idx, fx, fy) = Self::lookup(&Self::direction(x as f64 + dx, y as f64 + dy, width, height), face);
+                                faces[idx].sample(fx, fy)
+                            })
+                            .fold(Vec3::default(), |sum, color| sum + color);
+                        sum / 4.0
+                    },
+                };
+            }
+        }
+
+        film
+    }
+
+    /// Face and pixel on it ending up at (`x`, `y`) of the assembled image (the one
+    /// closest for equirectangular ones), None for the cross' empty corners. For
+    /// things that can't be blended, like the heatmap's work counts.
+    pub fn nearest(self, (x, y): (usize, usize), face: usize) -> Option<(usize, usize, usize)> {
+        match self {
+            CubeLayout::Cross => {
+                let idx = Self::CROSS.iter().position(|&cell| cell == (x / face, y / face))?;
+                Some((idx, x % face, y % face))
+            },
+            CubeLayout::Equirect => {
+                let (width, height) = self.size(face);
+                let (idx, fx, fy) = Self::lookup(&Self::direction(x as f64 + 0.5, y as f64 + 0.5, width, height), face);
+                Some((idx, (fx as usize).min(face - 1), (fy as usize).min(face - 1)))
+            },
+        }
+    }
+
+    /// Direction at (`x`, `y`) in pixels of a width x height equirectangular image,
+    /// the inverse of `EnvironmentMap`'s lookup
+    fn direction(x: f64, y: f64, width: usize, height: usize) -> Vec3 {
+        let phi = x / width as f64 * 2.0 * PI;
+        let theta = y / height as f64 * PI;
+        Vec3::new(-phi.cos() * theta.sin(), theta.cos(), phi.sin() * theta.sin())
+    }
+
+    /// Face `direction` goes through, and where on it in pixels (as `Film::sample`
+    /// takes them) for faces of `face` x `face` pixels
+    fn lookup(direction: &Vec3, face: usize) -> (usize, f64, f64) {
+        let (ax, ay, az) = (direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let (axis, component) = match (ax >= ay && ax >= az, ay >= az) {
+            (true, _) => (0, direction.x),
+            (false, true) => (1, direction.y),
+            (false, false) => (2, direction.z),
+        };
+        let idx = match component > 0.0 {
+            true => 2 * axis,
+            false => 2 * axis + 1,
+        };
+
+        // On the face's plane at distance 1, where it spans -1..1 both ways
+        let on_face = *direction / component.abs();
+        let s = 0.5 * (on_face.dot(&FACES[idx].right) + 1.0);
+        let t = 0.5 * (on_face.dot(&FACES[idx].up) + 1.0);
+        (idx, s * face as f64, (1.0 - t) * face as f64)
+    }
+}
@@ -0,0 +1,65 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use image::Rgb32FImage;
+
+use crate::color::Color;
+use crate::film::Film;
+use crate::vec3::Vec3;
+
+/// Captured lighting all around the scene: an equirectangular (latitude-longitude)
+/// HDR image, looked up by the direction rays escape the world in. Lights scenes
+/// like the place it was photographed in, and shows behind them.
+pub struct EnvironmentMap {
+    /// Linear radiance, row 0 straight up and the middle row the horizon
+    texels: Film,
+    /// Turn around the vertical axis, in radians
+    rotation: f64,
+    strength: f64
+}
+
+impl EnvironmentMap {
+    pub fn new(img: &Rgb32FImage) -> Self {
+        let mut texels = Film::new(img.width() as usize, img.height() as usize);
+        for (x, y, pixel) in img.enumerate_pixels() {
+            *texels.pixel_mut(x as usize, y as usize) = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+        }
+
+        Self { texels, rotation: 0.0, strength: 1.0 }
+    }
+
+    /// Load an HDR or EXR file, or any other format the image crate reads
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let img = image::open(path).map_err(|e| format!("can't load '{}': {}", path.display(), e))?;
+        match img.width() > 0 && img.height() > 0 {
+            true => Ok(Self::new(&img.to_rgb32f())),
+            false => Err(format!("'{}' is empty", path.display())),
+        }
+    }
+
+    /// Turn the environment around the vertical axis, i.e. to move the sun
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Multiply the light, for captures that weren't exposed for the scene
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// Light coming from `direction`
+    pub fn value(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit_vec();
+        let (width, height) = (self.texels.width(), self.texels.height());
+
+        // Around the vertical axis from -X (like Sphere::uv), then down from straight up
+        let phi = ((-direction.z).atan2(direction.x) + PI + self.rotation).rem_euclid(2.0 * PI);
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        // Blended between texels, so up close (or through a mirror) it isn't blocky
+        let x = (phi / (2.0 * PI)) * width as f64;
+        let y = (theta / PI) * height as f64;
+        self.strength * self.texels.sample(x, y)
+    }
+}
@@ -0,0 +1,50 @@
+use image::ImageError;
+use thiserror::Error;
+
+/// What can go wrong running the renderer, grouped by what a script driving it
+/// (say, a render farm wrapper) should do about it. Each kind has its own exit code:
+///
+/// | code | kind                                                            | retry? |
+/// |------|-----------------------------------------------------------------|--------|
+/// | 0    | success                                                         |        |
+/// | 1    | bisect-scene couldn't reproduce the problem                     | no     |
+/// | 2    | bad command line, config or input image                         | no     |
+/// | 3    | bad scene (unknown name, --add or --looks that don't parse)     | no     |
+/// | 4    | reading or writing a file failed                                | yes    |
+/// | 5    | not enough memory for the render                                | elsewhere, or smaller |
+/// | 130  | cancelled                                                       | yes    |
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Usage(String),
+    #[error("bad scene: {0}")]
+    Scene(String),
+    #[error("'{path}': {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("'{path}': {source}")]
+    Image { path: String, source: ImageError },
+    #[error("not enough memory to render at {width}x{height}")]
+    OutOfMemory { width: usize, height: usize },
+    /// The render was stopped before finishing. Being killed by Ctrl-C (SIGINT)
+    /// gives the same exit code in shells.
+    #[error("cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    NotReproduced(String)
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotReproduced(_) => 1,
+            Error::Usage(_) => 2,
+            Error::Scene(_) => 3,
+            Error::Io { .. } => 4,
+            // Only the image failing to load/save is worth retrying, not it being broken
+            Error::Image { source: ImageError::IoError(_), .. } => 4,
+            Error::Image { .. } => 2,
+            Error::OutOfMemory { .. } => 5,
+            Error::Cancelled => 130,
+        }
+    }
+}
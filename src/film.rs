@@ -0,0 +1,220 @@
+use std::ops::Range;
+use std::sync::Arc;
+use std::slice::ChunksMut;
+
+use crate::color::Color;
+
+use image::{Rgb32FImage, RgbImage};
+
+/// The image being rendered, in linear HDR color (averaged over the samples,
+/// but neither clamped nor gamma corrected). Row 0 is the top of the image.
+#[derive(Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![Color::default(); width * height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut Color {
+        &mut self.pixels[y * self.width + x]
+    }
+
+    /// All pixels, row by row from the top
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    /// Rows from top to bottom
+    pub fn rows_mut(&mut self) -> ChunksMut<'_, Color> {
+        self.pixels.chunks_mut(self.width)
+    }
+
+    /// Shrink by an integer factor, averaging every factor x factor block of
+    /// pixels (in linear color, before any clamping). Rendering at 2x and saving
+    /// downscaled is a cheap way to get extra anti-aliasing. Leftover rows and
+    /// columns that don't fill a whole block are dropped.
+    pub fn downscaled(&self, factor: usize) -> Film {
+        let factor = factor.max(1);
+        let mut film = Film::new(self.width / factor, self.height / factor);
+        let block = (factor * factor) as f64;
+
+        for y in 0..film.height {
+            for x in 0..film.width {
+                let mut sum = Color::default();
+                for dy in 0..factor {
+                    for dx in 0..factor {
+                        sum += self.pixel(x * factor + dx, y * factor + dy);
+                    }
+                }
+                *film.pixel_mut(x, y) = sum / block;
+            }
+        }
+
+        film
+    }
+
+    /// Stretch (or shrink) to another size with bilinear filtering, in linear color
+    pub fn resized(&self, width: usize, height: usize) -> Film {
+        let mut film = Film::new(width, height);
+        let (scale_x, scale_y) = (self.width as f64 / width as f64, self.height as f64 / height as f64);
+
+        for y in 0..height {
+            for x in 0..width {
+                // Pixel centers line up
+                *film.pixel_mut(x, y) = self.sample((x as f64 + 0.5) * scale_x, (y as f64 + 0.5) * scale_y);
+            }
+        }
+
+        film
+    }
+
+    /// Bilinear lookup at (`x`, `y`) in pixels, where pixel (0, 0) covers 0.0..1.0
+    /// in both and so has its center at (0.5, 0.5). Edges clamp.
+    pub fn sample(&self, x: f64, y: f64) -> Color {
+        let sx = (x - 0.5).clamp(0.0, (self.width - 1) as f64);
+        let sy = (y - 0.5).clamp(0.0, (self.height - 1) as f64);
+        let (x0, y0) = (sx as usize, sy as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (sx - x0 as f64, sy - y0 as f64);
+
+        let top = (1.0 - tx) * self.pixel(x0, y0) + tx * self.pixel(x1, y0);
+        let bottom = (1.0 - tx) * self.pixel(x0, y1) + tx * self.pixel(x1, y1);
+        (1.0 - ty) * top + ty * bottom
+    }
+
+    /// Contrast adaptive sharpening (as in AMD's FidelityFX CAS): every pixel is
+    /// pushed away from its 4 neighbours, less so where the neighbourhood already
+    /// has a lot of contrast, so edges get crisper without ringing or boosting noise.
+    /// `sharpness` goes from 0.0 (subtle) to 1.0 (strongest).
+    pub fn sharpened(&self, sharpness: f64) -> Film {
+        let sharpness = sharpness.clamp(0.0, 1.0);
+        let peak = -1.0 / (8.0 - 3.0 * sharpness);    // Lerp from 1/8 to 1/5
+        let mut film = self.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let center = self.pixel(x, y);
+                let neighbours = [
+                    self.pixel(x, y.saturating_sub(1)),
+                    self.pixel(x.saturating_sub(1), y),
+                    self.pixel((x + 1).min(self.width - 1), y),
+                    self.pixel(x, (y + 1).min(self.height - 1)),
+                ];
+
+                let mut sharpened = [0.0; 3];
+                for (k, value) in sharpened.iter_mut().enumerate() {
+                    // The amplitude is worked out on display range values, HDR
+                    // highlights are treated as plain white
+                    let around = neighbours.map(|pixel| pixel[k]);
+                    let min = around.iter().fold(center[k], |min, &v| min.min(v)).clamp(0.0, 1.0);
+                    let max = around.iter().fold(center[k], |max, &v| max.max(v)).clamp(0.0, 1.0);
+                    let amplitude = match max > 0.0 {
+                        true => (min.min(1.0 - max) / max).clamp(0.0, 1.0).sqrt(),
+                        false => 0.0,
+                    };
+
+                    let weight = amplitude * peak;
+                    *value = (weight * around.iter().sum::<f64>() + center[k]) / (4.0 * weight + 1.0);
+                }
+
+                *film.pixel_mut(x, y) = Color::new(sharpened[0], sharpened[1], sharpened[2]).max(&Color::default());
+            }
+        }
+
+        film
+    }
+
+    /// Split screen comparison: the left half of this film, the right half of
+    /// the other one (of the same size) and a white line between them
+    pub fn split_with(&self, other: &Film) -> Film {
+        assert!(self.width == other.width && self.height == other.height, "Can only split films of the same size");
+        let mut film = self.clone();
+        let middle = self.width / 2;
+        for y in 0..self.height {
+            for x in middle..self.width {
+                *film.pixel_mut(x, y) = match x == middle {
+                    true => crate::color::WHITE,
+                    false => other.pixel(x, y),
+                };
+            }
+        }
+
+        film
+    }
+
+    /// Back from an 8 bit image, undoing the gamma correction of `to_image`
+    pub fn from_image(img: &RgbImage) -> Film {
+        let mut film = Film::new(img.width() as usize, img.height() as usize);
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let linear = |value: u8| ((value as f64 + 0.5) / 256.0).powi(2);
+            *film.pixel_mut(x as usize, y as usize) = Color::new(linear(pixel[0]), linear(pixel[1]), linear(pixel[2]));
+        }
+
+        film
+    }
+
+    /// Quantize into an 8 bit image, gamma corrected
+    pub fn to_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            self.pixel(x as usize, y as usize).to_rgb(1.0)
+        })
+    }
+
+    /// Linear floating point copy, for formats that keep the HDR values (EXR)
+    pub fn to_hdr_image(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let pixel = self.pixel(x as usize, y as usize);
+            image::Rgb([pixel.x as f32, pixel.y as f32, pixel.z as f32])
+        })
+    }
+}
+
+/// Rectangle of pixels on the film
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub x: Range<usize>,
+    pub y: Range<usize>
+}
+
+/// Hook into the render loop for custom post effects (bloom, glare, denoisers, ...).
+/// Hooks run in the order they were added to the renderer, on the render's thread.
+pub trait PostProcess: Send + Sync {
+    /// A tile finished rendering. Pixels inside `region` are final for this pass,
+    /// the rest of the film may still be missing samples.
+    fn tile_done(&self, _film: &mut Film, _region: &Region) {}
+
+    /// A pass over the whole film finished
+    fn pass_done(&self, _film: &mut Film) {}
+}
+
+/// Shared hooks, i.e: one monitor following several renders
+impl<T: PostProcess + ?Sized> PostProcess for Arc<T> {
+    fn tile_done(&self, film: &mut Film, region: &Region) {
+        (**self).tile_done(film, region);
+    }
+
+    fn pass_done(&self, film: &mut Film) {
+        (**self).pass_done(film);
+    }
+}
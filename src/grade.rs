@@ -0,0 +1,234 @@
+//! Color grading applied when saving: exposure, contrast, saturation and an
+//! optional 3D LUT, so every render (or every job of a batch) can come out with
+//! its intended look without a trip through another program.
+//!
+//! Built-in presets are picked by name, custom grades live in a file of
+//! `name = value` lines like config files, starting from a preset if they want:
+//!
+//! ```text
+//! preset = punchy
+//! exposure = 0.3      # stops
+//! saturation = 0.9
+//! lut = teal-orange.cube
+//! ```
+
+use std::path::Path;
+
+use crate::assets::AssetPaths;
+use crate::color::Color;
+use crate::film::Film;
+
+/// How a film is graded, neutral by default
+#[derive(Clone)]
+pub struct Grade {
+    /// In stops, every one doubling the light
+    exposure: f64,
+    /// Slope of the curve around middle gray, in log space: above 1 darkens the
+    /// shadows and brightens the highlights
+    contrast: f64,
+    /// 0 is black and white, above 1 more colorful
+    saturation: f64,
+    lut: Option<Lut>
+}
+
+impl Default for Grade {
+    fn default() -> Self {
+        Self { exposure: 0.0, contrast: 1.0, saturation: 1.0, lut: None }
+    }
+}
+
+impl Grade {
+    /// Names of the built-in presets, for messages
+    pub const PRESETS: [&'static str; 6] = ["neutral", "punchy", "soft", "bleach", "mono", "bright"];
+
+    /// A built-in preset by name
+    pub fn preset(name: &str) -> Option<Grade> {
+        let (exposure, contrast, saturation) = match name {
+            "neutral" => (0.0, 1.0, 1.0),
+            "punchy" => (0.0, 1.25, 1.2),
+            "soft" => (0.0, 0.8, 0.9),
+            // Bleach bypass: harsh and washed out, like skipping the film's bleach bath
+            "bleach" => (0.0, 1.35, 0.45),
+            "mono" => (0.0, 1.1, 0.0),
+            "bright" => (0.5, 1.0, 1.0),
+            _ => return None,
+        };
+        Some(Grade { exposure, contrast, saturation, lut: None })
+    }
+
+    /// A grade file's contents, LUTs found through `assets`
+    pub fn parse(text: &str, assets: &AssetPaths) -> Result<Grade, String> {
+        let mut grade = Grade::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |e: String| format!("line {}: {}", idx + 1, e);
+            let (name, value) = line.split_once('=').ok_or_else(|| error("expected '<name> = <value>'".to_string()))?;
+            let (name, value) = (name.trim(), value.trim());
+            let number = || value.parse::<f64>().map_err(|_| error(format!("'{}' isn't a number", value)));
+            match name {
+                "preset" => {
+                    let lut = grade.lut.take();
+                    grade = Grade::preset(value)
+                        .ok_or_else(|| error(format!("unknown preset '{}', expected {}", value, Grade::PRESETS.join(", "))))?;
+                    grade.lut = lut;
+                },
+                "exposure" => grade.exposure = number()?,
+                "contrast" => grade.contrast = number()?,
+                "saturation" => grade.saturation = number()?,
+                "lut" => grade.lut = Some(Lut::open(&assets.resolve(value).map_err(error)?).map_err(error)?),
+                _ => return Err(error(format!("unknown setting '{}', expected preset, exposure, contrast, saturation or lut", name))),
+            }
+        }
+
+        Ok(grade)
+    }
+
+    /// A grade file's contents with its LUT's path swapped for what `map` makes of
+    /// it, for moving grades around (see bundle.rs). The LUT line's comment is dropped.
+    pub fn map_lut_file(text: &str, mut map: impl FnMut(&str) -> Result<String, String>) -> Result<String, String> {
+        let mut mapped = String::new();
+        for (idx, line) in text.lines().enumerate() {
+            let setting = line.split('#').next().unwrap_or("");
+            match setting.split_once('=') {
+                Some((name, value)) if name.trim() == "lut" => {
+                    let lut = map(value.trim()).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+                    mapped += &format!("lut = {}", lut);
+                },
+                _ => mapped += line,
+            }
+            mapped.push('\n');
+        }
+
+        Ok(mapped)
+    }
+
+    /// Graded copy of `film`, still linear
+    pub fn graded(&self, film: &Film) -> Film {
+        let mut film = film.clone();
+        for pixel in film.pixels_mut() {
+            *pixel = self.apply(*pixel);
+        }
+
+        film
+    }
+
+    fn apply(&self, color: Color) -> Color {
+        const MIDDLE_GRAY: f64 = 0.18;
+
+        let color = 2f64.powf(self.exposure) * color;
+        let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+        let gray = Color::new(luminance, luminance, luminance);
+        // Both written so that neutral settings leave the color exactly as it was
+        let color = (self.saturation * color + (1.0 - self.saturation) * gray).max(&Color::default());
+        // 0 · 0^(contrast - 1) is NaN when contrast is below 1, black stays black
+        let curve = |value: f64| match value > 0.0 {
+            true => value * (value / MIDDLE_GRAY).powf(self.contrast - 1.0),
+            false => 0.0,
+        };
+        let color = Color::new(curve(color.x), curve(color.y), curve(color.z));
+
+        // LUTs are made for display values, gamma encoded like `Film::to_image` does
+        match &self.lut {
+            Some(lut) => {
+                let encoded = lut.at(Color::new(color.x.min(1.0).sqrt(), color.y.min(1.0).sqrt(), color.z.min(1.0).sqrt()));
+                encoded * encoded
+            },
+            None => color,
+        }
+    }
+}
+
+/// A 3D lookup table from a .cube file (the Adobe/Resolve format most grading
+/// tools export), blended trilinearly between its entries
+#[derive(Clone)]
+pub struct Lut {
+    size: usize,
+    /// Red changing fastest, then green, then blue
+    entries: Vec<Color>
+}
+
+impl Lut {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("can't read '{}': {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("LUT '{}' {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut entries = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None | Some("TITLE") => (),
+                Some("LUT_3D_SIZE") => size = words.next().and_then(|size| size.parse::<usize>().ok()),
+                Some("DOMAIN_MIN") if line.split_whitespace().skip(1).all(|value| value.parse::<f64>() == Ok(0.0)) => (),
+                Some("DOMAIN_MAX") if line.split_whitespace().skip(1).all(|value| value.parse::<f64>() == Ok(1.0)) => (),
+                Some(keyword @ ("LUT_1D_SIZE" | "DOMAIN_MIN" | "DOMAIN_MAX")) => return Err(format!("line {}: {} isn't supported", idx + 1, keyword)),
+                Some(_) => {
+                    let values: Vec<f64> = line.split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| format!("line {}: expected 'r g b'", idx + 1))?;
+                    let [r, g, b] = values[..] else {
+                        return Err(format!("line {}: expected 'r g b'", idx + 1));
+                    };
+                    entries.push(Color::new(r, g, b));
+                },
+            }
+        }
+
+        match size {
+            Some(size) if size < 2 => Err("needs a LUT_3D_SIZE of at least 2".to_string()),
+            Some(size) if entries.len() == size * size * size => Ok(Self { size, entries }),
+            Some(size) => Err(format!("has {} entries, a size of {} needs {}", entries.len(), size, size * size * size)),
+            None => Err("has no LUT_3D_SIZE".to_string()),
+        }
+    }
+
+    /// Where `color` (in 0..=1) maps to
+    pub fn at(&self, color: Color) -> Color {
+        let last = (self.size - 1) as f64;
+        let scaled = [color.x, color.y, color.z].map(|value| value.clamp(0.0, 1.0) * last);
+        let low = scaled.map(|value| (value as usize).min(self.size - 2));
+        let blend = [0, 1, 2].map(|axis| scaled[axis] - low[axis] as f64);
+
+        let mut sum = Color::default();
+        for corner in 0..8 {
+            let (r, g, b) = (corner & 1, (corner >> 1) & 1, corner >> 2);
+            let weight = |bit: usize, axis: usize| match bit {
+                1 => blend[axis],
+                _ => 1.0 - blend[axis],
+            };
+            let entry = self.entries[(low[0] + r) + (low[1] + g) * self.size + (low[2] + b) * self.size * self.size];
+            sum += weight(r, 0) * weight(g, 1) * weight(b, 2) * entry;
+        }
+
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soft_contrast_keeps_black_and_red_with_a_lut() {
+        // Every corner maps to itself, red changing fastest
+        let identity = Lut::parse("LUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n").unwrap();
+        let mut grade = Grade::preset("soft").unwrap();
+        grade.lut = Some(identity);
+
+        let black = grade.apply(Color::default());
+        assert_eq!((black.x, black.y, black.z), (0.0, 0.0, 0.0));
+
+        // Lower contrast dims it and desaturating pulls a little green and blue
+        // into it, but it stays red
+        let red = grade.apply(Color::new(1.0, 0.0, 0.0));
+        assert!(red.x > 10.0 * red.y && red.y == red.z, "({}, {}, {})", red.x, red.y, red.z);
+    }
+}
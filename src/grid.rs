@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+/// Average amount of objects per cell the grid's resolution aims for
+const OBJECTS_PER_CELL: f64 = 2.0;
+/// Cap on the cells per axis, so a few huge objects can't blow up memory
+const MAX_RESOLUTION: usize = 128;
+
+/// Uniform grid: the scene's box is cut into equally sized cells, each listing
+/// the objects overlapping it. Building is a single pass over the objects, so
+/// it's the cheapest structure to rebuild for scenes that change every frame.
+/// Rays step through the cells they cross with a 3D-DDA.
+pub struct UniformGrid {
+    objects: Vec<Arc<dyn Hit>>,
+    bbox: Aabb,
+    resolution: [usize; 3],
+    /// Flattened x-major cells, each holding indices into `objects`
+    cells: Vec<Vec<usize>>
+}
+
+impl UniformGrid {
+    /// Build a grid over the objects. Panics if there are none.
+    pub fn new(objects: Vec<Arc<dyn Hit>>) -> Self {
+        assert!(!objects.is_empty(), "Can't build a grid without objects");
+
+        let boxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let bbox = boxes.iter().copied().reduce(|a, b| a.surrounding(&b)).unwrap();
+
+        // Pick cubic-ish cells so that there are about OBJECTS_PER_CELL objects per cell
+        let extent = bbox.max - bbox.min;
+        let volume = (extent.x * extent.y * extent.z).max(f64::MIN_POSITIVE);
+        let cells_per_unit = (OBJECTS_PER_CELL.recip() * objects.len() as f64 / volume).cbrt();
+        let resolution = [0, 1, 2].map(|axis| {
+            ((extent[axis] * cells_per_unit).round() as usize).clamp(1, MAX_RESOLUTION)
+        });
+
+        let mut grid = Self {
+            objects,
+            bbox,
+            resolution,
+            cells: vec![Vec::new(); resolution[0] * resolution[1] * resolution[2]]
+        };
+
+        for (idx, object_bbox) in boxes.iter().enumerate() {
+            let min = [0, 1, 2].map(|axis| grid.cell_coord(object_bbox.min[axis], axis));
+            let max = [0, 1, 2].map(|axis| grid.cell_coord(object_bbox.max[axis], axis));
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        let cell = grid.cell_index([x, y, z]);
+                        grid.cells[cell].push(idx);
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    /// Which cell (along `axis`) a coordinate falls in, clamped to the grid
+    fn cell_coord(&self, value: f64, axis: usize) -> usize {
+        let extent = self.bbox.axis(axis);
+        let offset = (value - extent.min) / extent.size();
+        let coord = (offset * self.resolution[axis] as f64) as isize;
+        coord.clamp(0, self.resolution[axis] as isize - 1) as usize
+    }
+
+    fn cell_index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (x * self.resolution[1] + y) * self.resolution[2] + z
+    }
+}
+
+impl Hit for UniformGrid {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let inside = self.bbox.clip(ray, interval)?;
+        let entry = ray.at(inside.min);
+
+        // 3D-DDA setup: for every axis, the t at which the ray crosses into the
+        // next cell (t_next) and how much t it takes to cross a whole cell (t_delta)
+        let mut cell = [0usize; 3];
+        let mut step = [0isize; 3];
+        let mut t_next = [f64::INFINITY; 3];
+        let mut t_delta = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            cell[axis] = self.cell_coord(entry[axis], axis);
+            let cell_size = self.bbox.axis(axis).size() / self.resolution[axis] as f64;
+            let cell_min = self.bbox.min[axis] + cell[axis] as f64 * cell_size;
+            let direction = ray.direction[axis];
+
+            if direction > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = cell_size / direction;
+                t_next[axis] = (cell_min + cell_size - ray.origin[axis]) / direction;
+            } else if direction < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -cell_size / direction;
+                t_next[axis] = (cell_min - ray.origin[axis]) / direction;
+            }
+        }
+
+        let mut interval = *interval;
+        let mut closest: Option<HitRecord> = None;
+        loop {
+            // Objects span many cells, so a hit found here may lie in a later cell -
+            // it's only final once the ray has left the cell the hit is in.
+            for &idx in &self.cells[self.cell_index(cell)] {
+                if let Some(hit) = self.objects[idx].try_hit(ray, &interval) {
+                    interval.max = hit.t;
+                    closest = Some(hit);
+                }
+            }
+
+            let axis = match (t_next[0], t_next[1], t_next[2]) {
+                (x, y, z) if x <= y && x <= z => 0,
+                (_, y, z) if y <= z => 1,
+                _ => 2,
+            };
+
+            if closest.as_ref().is_some_and(|hit| hit.t <= t_next[axis]) || t_next[axis] > inside.max {
+                return closest;
+            }
+
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= self.resolution[axis] as isize {
+                return closest;
+            }
+            cell[axis] = next as usize;
+            t_next[axis] += t_delta[axis];
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -1,63 +1,520 @@
-use std::ops::Range;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 
+use crate::aabb::Aabb;
+use crate::bvh::{BvhBuilder, BvhNode};
+use crate::grid::UniformGrid;
+use crate::kdtree::KdTree;
 use crate::color;
 use crate::color::Color;
+use crate::background::{Background, Gradient};
+use crate::interval::Interval;
+use crate::light::PointLight;
 use crate::vec3::{Point3, Vec3};
-use crate::ray::Ray;
+use crate::ray::{Lobe, Ray};
 use crate::material::{Material, Scatter};
 
 pub trait Hit: Send + Sync {
-    fn try_hit(&self, ray: &Ray, interval: &Range<f64>) -> Option<HitRecord>;
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord>;
+
+    /// Box fully enclosing the geometry, used by acceleration structures
+    fn bounding_box(&self) -> Aabb;
 }
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub t: f64,
     pub p: Point3,
     pub normal: Vec3,
+    /// The actual surface's normal, on the same side as `normal`. Only differs
+    /// from it when the geometry sets a shading normal.
+    pub geometric_normal: Vec3,
     pub front_face: bool,
-    pub material: Arc<dyn Material>
+    /// Surface coordinates of p, both in 0.0..=1.0
+    pub u: f64,
+    pub v: f64,
+    pub material: Arc<dyn Material>,
+    /// Direction along which u grows on the surface, for geometry that knows it
+    pub tangent: Option<Vec3>,
+    /// Which object of the world was hit, filled in by HittableList
+    pub object: Option<ObjectId>
 }
 
 impl HitRecord {
-    /// p is point hit when ray travelled t time
+    /// p is point hit when ray travelled t time and (u, v) its surface coordinates
     /// Calculates the front face internally
-    pub fn new(t: f64, p: Point3, outward_normal: Vec3, ray: &Ray, material: Arc<dyn Material>) -> Self {
+    pub fn new(
+        t: f64,
+        p: Point3,
+        outward_normal: Vec3,
+        (u, v): (f64, f64),
+        ray: &Ray,
+        material: Arc<dyn Material>
+    ) -> Self {
         let front_face = ray.direction.dot(&outward_normal) < 0.0;
         let normal = match front_face {
             true => outward_normal,
             false => -outward_normal
         };
 
-        Self { t, p, normal, front_face, material }
+        Self { t, p, normal, geometric_normal: normal, front_face, u, v, material, tangent: None, object: None }
+    }
+
+    /// Set the direction along which u grows (doesn't need to be normalized or
+    /// perpendicular to the normal). Ignored if it's degenerate, i.e: at a pole.
+    pub fn with_tangent(mut self, tangent: Vec3) -> Self {
+        if tangent.len_squared() > 1e-16 {
+            self.tangent = Some(tangent.unit_vec());
+        }
+        self
+    }
+
+    /// Replace the normal used for shading (i.e: interpolated vertex normals) while
+    /// keeping it on the same side of the surface as the geometric normal.
+    pub fn set_shading_normal(&mut self, shading_normal: Vec3) {
+        self.normal = match self.front_face {
+            true => shading_normal,
+            false => -shading_normal
+        };
     }
 
     /// Calls the hit material's underlying scatter function
     pub fn scatter(&self, ray: &Ray) -> Option<Scatter> {
         self.material.scatter(self, ray)
     }
+
+    /// Calls the hit material's underlying emitted function
+    pub fn emitted(&self) -> Color {
+        self.material.emitted(self)
+    }
+
+    /// Calls the hit material's underlying lit function
+    pub fn lit(&self, ray: &Ray, to_light: &Vec3) -> Color {
+        self.material.lit(self, ray, to_light)
+    }
+}
+
+/// What find_ray_color trusts where the shading normal (interpolated mesh normals,
+/// ...) disagrees with the geometric one, which happens around silhouettes
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum NormalPolicy {
+    /// Rays are pushed off the real surface, and bounces the shading normal sends
+    /// to the other side of the real surface (or vice versa) are absorbed. Never
+    /// leaks light through the surface.
+    #[default]
+    Geometric,
+    /// Rays are pushed off to the side the shading normal says they leave through,
+    /// and are kept even if that's through the real surface. Smoother silhouettes on
+    /// coarse meshes, at the cost of occasionally leaking light.
+    Shading
+}
+
+/// What rays hitting an object from behind (against its outward normal) see
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum BackFaces {
+    /// The material, same as the front
+    #[default]
+    Shaded,
+    /// Nothing, they absorb everything and emit nothing (i.e: one sided lights)
+    Black,
+    /// Straight through, as if the back faces weren't there (i.e: seeing into
+    /// a room through its wall, open meshes)
+    Culled
+}
+
+impl BackFaces {
+    /// Hit the geometry, handling its back faces accordingly
+    fn try_hit(self, geometry: &dyn Hit, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let mut hit = geometry.try_hit(ray, interval)?;
+        match self {
+            BackFaces::Shaded => Some(hit),
+            BackFaces::Black => {
+                if !hit.front_face {
+                    hit.material = Arc::clone(&ABSORBING);
+                }
+                Some(hit)
+            },
+            BackFaces::Culled => {
+                // Look past every back face until a front one shows up
+                let mut interval = *interval;
+                while !hit.front_face {
+                    interval.min = hit.t.next_up();
+                    hit = geometry.try_hit(ray, &interval)?;
+                }
+                Some(hit)
+            },
+        }
+    }
+}
+
+/// Black back faces' material
+struct Absorbing;
+
+/// The one Absorbing every black back face shares, so hitting one doesn't allocate
+static ABSORBING: LazyLock<Arc<dyn Material>> = LazyLock::new(|| Arc::new(Absorbing));
+
+impl Material for Absorbing {
+    fn scatter(&self, _hit: &HitRecord, _ray: &Ray) -> Option<Scatter> {
+        None
+    }
+}
+
+/// Self-intersection epsilon relative to the scene's size and how far from
+/// the origin/ray start a hit is. Tuned so the book's scenes end up with about
+/// the same epsilon as the old hard-coded 0.001.
+const EPSILON_SCALE: f64 = 5e-7;
+
+thread_local! {
+    // Object the last path traced on this thread hit most recently, for naming the
+    // culprit when a pixel panics
+    static LAST_HIT: std::cell::Cell<Option<ObjectId>> = const { std::cell::Cell::new(None) };
+}
+
+/// The object this thread's paths hit last since the previous call, and forget it
+pub fn take_last_hit() -> Option<ObjectId> {
+    LAST_HIT.take()
+}
+
+/// Handle to an object in a HittableList. Stays valid (and is never reused)
+/// while other objects are added, removed or replaced.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ObjectId(u64);
+
+/// Entry of a HittableList: the geometry and how it can be referred to
+struct Object {
+    id: ObjectId,
+    /// Optional hierarchical path, i.e: "/city/block_3/lamp_post_12"
+    name: Option<String>,
+    geometry: Arc<dyn Hit>,
+    back_faces: BackFaces
+}
+
+/// Geometry tagged with its id, so hits coming out of an accelerator know what
+/// they hit, and with how its back faces are handled
+struct Tagged {
+    id: ObjectId,
+    geometry: Arc<dyn Hit>,
+    back_faces: BackFaces
+}
+
+impl Hit for Tagged {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let mut hit = self.back_faces.try_hit(self.geometry.as_ref(), ray, interval)?;
+        hit.object = Some(self.id);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.geometry.bounding_box()
+    }
+}
+
+/// Spatial structure a HittableList can use to find hits instead of testing
+/// every object one by one
+pub enum Accelerator {
+    Bvh(BvhNode),
+    KdTree(KdTree),
+    Grid(UniformGrid)
+}
+
+impl Accelerator {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        match self {
+            Accelerator::Bvh(bvh) => bvh.try_hit(ray, interval),
+            Accelerator::KdTree(kdtree) => kdtree.try_hit(ray, interval),
+            Accelerator::Grid(grid) => grid.try_hit(ray, interval),
+        }
+    }
+}
+
+/// When paths stop bouncing
+#[derive(Clone, Copy, Debug)]
+pub struct PathLimits {
+    /// Paths are cut here whatever light they could still bring back
+    pub max_bounces: u32,
+    /// Paths whose throughput (the light they'd still bring back, in their brightest
+    /// channel) falls below this play russian roulette: they go on with a chance of
+    /// throughput / min_throughput, the survivors weighted up to keep the average.
+    /// Dark scenes stop tracing paths that wouldn't matter anyway. 0 turns it off.
+    pub min_throughput: f64
+}
+
+impl PathLimits {
+    pub const DEFAULT: PathLimits = PathLimits { max_bounces: 50, min_throughput: 0.05 };
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Light a camera ray brought back
+#[derive(Clone, Copy, Default)]
+pub struct Traced {
+    pub total: Color,
+    /// The part that came straight from emitters or the sky, or off the first
+    /// surface hit; the rest of the total bounced around more before
+    pub direct: Color,
+    /// The part from emitters (or the sky) the camera sees itself, before any bounce
+    pub emission: Color,
+    /// How the first surface hit scattered the ray, None if it didn't. The rest of
+    /// the total (besides the emission) came in through that lobe.
+    pub lobe: Option<Lobe>
 }
 
 #[derive(Default)]
 pub struct HittableList {
-    hittables: Vec<Box<dyn Hit>>
+    hittables: Vec<Object>,
+    next_id: u64,
+    bbox: Option<Aabb>,
+    accelerator: Option<Accelerator>,
+    /// What rays escaping the world see, None for the book's sky gradient
+    background: Option<Arc<dyn Background>>,
+    /// Lights only reached through shadow rays, see light.rs
+    lights: Vec<PointLight>,
+    normal_policy: NormalPolicy
 }
 
 impl HittableList {
     /// The lifetime 'static here means that geometry owns all it's data
-    pub fn add(&mut self, geometry: impl Hit + 'static) {
-        self.hittables.push(Box::new(geometry));
+    pub fn add(&mut self, geometry: impl Hit + 'static) -> ObjectId {
+        self.add_shared(Arc::new(geometry))
+    }
+
+    /// Add geometry under a name, usually a '/' separated path like "/city/block_3/lamp_post_12"
+    pub fn add_named(&mut self, name: &str, geometry: impl Hit + 'static) -> ObjectId {
+        let id = self.add(geometry);
+        self.hittables.last_mut().unwrap().name = Some(name.to_string());
+        id
+    }
+
+    /// Add geometry that may also be used elsewhere (i.e: the same mesh in two worlds)
+    pub fn add_shared(&mut self, geometry: Arc<dyn Hit>) -> ObjectId {
+        let bbox = geometry.bounding_box();
+        self.bbox = Some(match self.bbox {
+            Some(scene_bbox) => scene_bbox.surrounding(&bbox),
+            None => bbox,
+        });
+
+        let id = ObjectId(self.next_id);
+        self.next_id += 1;
+        self.hittables.push(Object { id, name: None, geometry, back_faces: BackFaces::Shaded });
+        self.accelerator = None;    // The tree no longer covers everything
+
+        id
+    }
+
+    /// Remove an object from the world, returning it if it was there
+    pub fn remove(&mut self, id: ObjectId) -> Option<Arc<dyn Hit>> {
+        let idx = self.hittables.iter().position(|object| object.id == id)?;
+        let object = self.hittables.remove(idx);
+        self.edited();
+
+        Some(object.geometry)
+    }
+
+    /// Swap the geometry of an object, keeping its id. Returns the old geometry,
+    /// or None (leaving the world untouched) if there's no such object.
+    pub fn replace(&mut self, id: ObjectId, geometry: impl Hit + 'static) -> Option<Arc<dyn Hit>> {
+        let object = self.hittables.iter_mut().find(|object| object.id == id)?;
+        let old = std::mem::replace(&mut object.geometry, Arc::new(geometry));
+        self.edited();
+
+        Some(old)
+    }
+
+    /// Choose what rays see when hitting the object from behind (see BackFaces).
+    /// Returns false if there's no such object.
+    pub fn set_back_faces(&mut self, id: ObjectId, back_faces: BackFaces) -> bool {
+        let Some(object) = self.hittables.iter_mut().find(|object| object.id == id) else {
+            return false;
+        };
+        object.back_faces = back_faces;
+        self.accelerator = None;    // It has the old setting baked in
+
+        true
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&Arc<dyn Hit>> {
+        self.object(id).map(|object| &object.geometry)
+    }
+
+    /// Name the object was added with, if any
+    pub fn name(&self, id: ObjectId) -> Option<&str> {
+        self.object(id).and_then(|object| object.name.as_deref())
+    }
+
+    /// Find the object with exactly this name
+    pub fn find(&self, name: &str) -> Option<ObjectId> {
+        self.hittables.iter()
+            .find(|object| object.name.as_deref() == Some(name))
+            .map(|object| object.id)
+    }
+
+    /// Every object named `path` or living under it in the hierarchy, so "/city"
+    /// selects "/city/block_3/lamp_post_12" but not "/city_2".
+    pub fn under<'a>(&'a self, path: &'a str) -> impl Iterator<Item = ObjectId> + 'a {
+        let path = path.trim_end_matches('/');
+        self.hittables.iter()
+            .filter(move |object| match object.name.as_deref() {
+                Some(name) => name == path || name.strip_prefix(path).is_some_and(|rest| rest.starts_with('/')),
+                None => false,
+            })
+            .map(|object| object.id)
+    }
+
+    /// Iterate over every object in the world, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &Arc<dyn Hit>)> {
+        self.hittables.iter().map(|object| (object.id, &object.geometry))
+    }
+
+    /// Copy of the world with only the objects `keep` accepts, sharing their
+    /// geometry and keeping their ids and names. Has no accelerator yet.
+    pub fn filtered(&self, keep: impl Fn(ObjectId) -> bool) -> HittableList {
+        let mut world = HittableList {
+            next_id: self.next_id,
+            background: self.background.clone(),
+            lights: self.lights.clone(),
+            normal_policy: self.normal_policy,
+            ..Default::default()
+        };
+        for object in self.hittables.iter().filter(|object| keep(object.id)) {
+            world.hittables.push(Object {
+                id: object.id,
+                name: object.name.clone(),
+                geometry: object.geometry.clone(),
+                back_faces: object.back_faces
+            });
+        }
+        world.edited();
+
+        world
+    }
+
+    fn object(&self, id: ObjectId) -> Option<&Object> {
+        self.hittables.iter().find(|object| object.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hittables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hittables.is_empty()
+    }
+
+    /// Objects changed in place: recompute the world's bounds and drop the
+    /// (now stale) accelerator - build it again once done editing.
+    fn edited(&mut self) {
+        self.bbox = self.hittables.iter()
+            .map(|object| object.geometry.bounding_box())
+            .reduce(|a, b| a.surrounding(&b));
+        self.accelerator = None;
+    }
+
+    /// Build a BVH over everything added so far, which `shoot_ray` will use
+    /// instead of testing every object. Editing the world afterwards drops it.
+    pub fn build_bvh(&mut self) {
+        self.build_bvh_with(&BvhBuilder::default());
+    }
+
+    /// Same as `build_bvh`, but with a custom configured builder
+    pub fn build_bvh_with(&mut self, builder: &BvhBuilder) {
+        if !self.hittables.is_empty() {
+            self.accelerator = Some(Accelerator::Bvh(builder.build(self.tagged_objects())));
+        }
+    }
+
+    /// Build a kd-tree over everything added so far, used by `shoot_ray` in
+    /// place of a BVH (building one replaces the other).
+    pub fn build_kdtree(&mut self) {
+        if !self.hittables.is_empty() {
+            self.accelerator = Some(Accelerator::KdTree(KdTree::new(self.tagged_objects())));
+        }
+    }
+
+    /// Build a uniform grid over everything added so far, used by `shoot_ray`
+    /// in place of any other accelerator. Cheapest to rebuild after edits.
+    pub fn build_grid(&mut self) {
+        if !self.hittables.is_empty() {
+            self.accelerator = Some(Accelerator::Grid(UniformGrid::new(self.tagged_objects())));
+        }
+    }
+
+    /// Objects moved in place (through interior mutability): update the world's
+    /// bounds and refit the BVH to them instead of rebuilding it. Other
+    /// accelerators can't be refitted, so they're rebuilt.
+    pub fn refit(&mut self) {
+        let accelerator = self.accelerator.take();
+        self.edited();
+
+        self.accelerator = match accelerator {
+            Some(Accelerator::Bvh(mut bvh)) => {
+                bvh.refit();
+                Some(Accelerator::Bvh(bvh))
+            },
+            Some(Accelerator::KdTree(_)) => Some(Accelerator::KdTree(KdTree::new(self.tagged_objects()))),
+            Some(Accelerator::Grid(_)) => Some(Accelerator::Grid(UniformGrid::new(self.tagged_objects()))),
+            None => None,
+        };
+    }
+
+    /// The objects wrapped so that hits coming out of an accelerator know their id
+    fn tagged_objects(&self) -> Vec<Arc<dyn Hit>> {
+        self.hittables.iter()
+            .map(|object| {
+                let tagged = Tagged { id: object.id, geometry: Arc::clone(&object.geometry), back_faces: object.back_faces };
+                Arc::new(tagged) as Arc<dyn Hit>
+            })
+            .collect()
+    }
+
+    /// Replace the sky gradient with another background (a solid color, a captured
+    /// environment, ... see background.rs)
+    pub fn set_background(&mut self, background: Arc<dyn Background>) {
+        self.background = Some(background);
+    }
+
+    /// Add a point light, lighting every surface that can see it
+    pub fn add_light(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+
+    /// How to handle shading normals that disagree with the geometry (see NormalPolicy)
+    pub fn set_normal_policy(&mut self, policy: NormalPolicy) {
+        self.normal_policy = policy;
+    }
+
+    /// Box around everything in the world, None if it is empty
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    /// Epsilon used to avoid self-intersections around point p, reached after a
+    /// ray travelled `distance`. Floating point error grows with the magnitude of
+    /// the coordinates involved, so millimeter sized scenes get a tiny epsilon
+    /// and kilometer sized ones a large one.
+    pub fn epsilon(&self, p: &Point3, distance: f64) -> f64 {
+        let extent = self.bbox.map_or(0.0, |bbox| (bbox.max - bbox.min).len());
+        let magnitude = p.x.abs().max(p.y.abs()).max(p.z.abs());
+
+        EPSILON_SCALE * (extent + magnitude + distance)
     }
 
     /// Shoot ray into world and return the closest element it hits
-    pub fn shoot_ray(&self, ray: &Ray, mut interval: Range<f64>) -> Option<HitRecord> {
+    pub fn shoot_ray(&self, ray: &Ray, mut interval: Interval) -> Option<HitRecord> {
+        if let Some(accelerator) = &self.accelerator {
+            return accelerator.try_hit(ray, &interval);
+        }
+
         // Shoot the ray at every "Hit" object in the list.
-        // To get the object which is hit first (has the lowest 't') the interval.end
+        // To get the object which is hit first (has the lowest 't') the interval.max
         // decreases every time a hit is sucessfull.
         let mut hit_record = None;
-        for hittable in &self.hittables {
-            if let Some(hit) = hittable.try_hit(&ray, &interval) {
-                interval.end = hit.t;
+        for object in &self.hittables {
+            if let Some(mut hit) = object.back_faces.try_hit(object.geometry.as_ref(), ray, &interval) {
+                interval.max = hit.t;
+                hit.object = Some(object.id);
                 hit_record = Some(hit);
             }
         }
@@ -65,32 +522,203 @@ impl HittableList {
         hit_record
     }
 
-    /// Shoot ray into world and simulate bouncing and scattering for a max of
-    /// `bounces` child rays.
-    pub fn find_ray_color(&self, mut ray: Ray, mut bounces: u32) -> Color {
+    /// Shoot ray into world and simulate bouncing and scattering until the path
+    /// runs into `limits`
+    pub fn find_ray_color(&self, ray: Ray, limits: PathLimits) -> Color {
+        self.trace(ray, limits).total
+    }
+
+    /// Same as `find_ray_color`, also telling where the light came from (see Traced)
+    pub fn trace(&self, mut ray: Ray, limits: PathLimits) -> Traced {
         // Ray starts with full energy, which is white {1.0, 1.0, 1.0} and
         // gets attenuated each hit (how much depends on the hittable albedo)
         let mut ray_color = color::WHITE;
+        let mut radiance = color::BLACK;    // Light picked up along the way
+        let mut direct = color::BLACK;
+        let mut emission = color::BLACK;
+        let mut lobe = None;
+        let mut t_min = self.epsilon(&ray.origin, 0.0);
+        let max_bounces = limits.max_bounces;
+        let mut bounces = max_bounces;
         while bounces > 0 {
-            match self.shoot_ray(&ray, 0.001..f64::INFINITY) {
+            // Camera rays and the ones leaving the first surface they hit
+            let is_direct = max_bounces - bounces <= 1;
+            match self.shoot_ray(&ray, Interval::new(t_min, f64::INFINITY)) {
                 Some(hit) => {
-                    // match hit.scatter(&hit, &ray) {
+                    LAST_HIT.set(hit.object);
+                    let emitted = ray_color * hit.emitted();
+                    radiance += emitted;
+                    if is_direct {
+                        direct += emitted;
+                    }
+                    if bounces == max_bounces {
+                        emission += emitted;
+                    }
+                    // Point lights bounced once off the first surface are direct light too
+                    let lit = ray_color * self.point_lights(&ray, &hit);
+                    radiance += lit;
+                    if bounces == max_bounces {
+                        direct += lit;
+                    }
                     match hit.scatter(&ray) {
                         Some((scattered, attenuation)) => {
+                            let Some((scattered, epsilon)) = self.leave(&ray, &hit, scattered) else {
+                                return Traced { total: radiance, direct, emission, lobe };
+                            };
+                            lobe = lobe.or(Some(scattered.lobe));
+                            t_min = epsilon;
+
                             // If ray hit something and bounced, shoot the scattered ray
-                            bounces = bounces - 1;
+                            bounces -= 1;
                             ray = scattered;
                             ray_color *= attenuation;    // Attenuate ray color
+
+                            // Little left to bring back: roulette, not worth a full path
+                            let throughput = ray_color.x.max(ray_color.y).max(ray_color.z);
+                            if throughput < limits.min_throughput {
+                                let survival = throughput / limits.min_throughput;
+                                match fastrand::f64() < survival {
+                                    true => ray_color /= survival,
+                                    false => return Traced { total: radiance, direct, emission, lobe },
+                                }
+                            }
                         },
                         // Otherwise ray was absorbed and lost all energy
-                        None => return color::BLACK,
+                        None => return Traced { total: radiance, direct, emission, lobe },
                     };
                 },
                 // Ray returned to camera - we found it's color.
-                None => return ray_color * Color::from(&ray),
+                None => {
+                    let background = ray_color * self.sky(&ray);
+                    let direct = match is_direct {
+                        true => direct + background,
+                        false => direct,
+                    };
+                    let emission = match bounces == max_bounces {
+                        true => emission + background,
+                        false => emission,
+                    };
+                    return Traced { total: radiance + background, direct, emission, lobe };
+                },
+            };
+        }
+
+        Traced { total: radiance, direct, emission, lobe }    // If ray exhausts it's bounces, it lost all energy
+    }
+
+    /// Cheap fixed function stand-in for `trace`, for quick feedback: specular chains
+    /// (mirrors, glass) are followed as usual, but the first diffuse bounce is the
+    /// last. It sees lights directly and the sky where nothing occludes it within
+    /// `ao_distance`, the rest of the bounce light is left out. Renders come out
+    /// darker than the path tracer's in enclosed scenes, and small lights (only
+    /// found by chance) stay noisy.
+    pub fn trace_preview(&self, mut ray: Ray, ao_distance: f64) -> Traced {
+        // Enough for a ray through a couple of glass spheres
+        const MAX_SPECULAR: u32 = 8;
+
+        let mut ray_color = color::WHITE;
+        let mut radiance = color::BLACK;
+        let mut emission = color::BLACK;
+        let mut lobe = None;
+        let mut t_min = self.epsilon(&ray.origin, 0.0);
+        for depth in 0..MAX_SPECULAR {
+            let Some(hit) = self.shoot_ray(&ray, Interval::new(t_min, f64::INFINITY)) else {
+                let background = ray_color * self.sky(&ray);
+                if depth == 0 {
+                    emission = background;
+                }
+                return Traced { total: radiance + background, direct: radiance + background, emission, lobe };
+            };
+            LAST_HIT.set(hit.object);
+
+            let emitted = ray_color * hit.emitted();
+            radiance += emitted + ray_color * self.point_lights(&ray, &hit);
+            if depth == 0 {
+                emission = emitted;
+            }
+            let Some((scattered, attenuation)) = hit.scatter(&ray) else {
+                break;
+            };
+            let Some((scattered, epsilon)) = self.leave(&ray, &hit, scattered) else {
+                break;
+            };
+            lobe = lobe.or(Some(scattered.lobe));
+            ray_color *= attenuation;
+            (ray, t_min) = (scattered, epsilon);
+            if ray.lobe != Lobe::Diffuse {
+                continue;
+            }
+
+            // The one diffuse bounce: lights, or the sky unless something's in the way
+            radiance += ray_color * match self.shoot_ray(&ray, Interval::new(t_min, f64::INFINITY)) {
+                Some(occluder) if occluder.t * ray.direction.len() < ao_distance => occluder.emitted(),
+                Some(occluder) => occluder.emitted() + self.sky(&ray),
+                None => self.sky(&ray),
+            };
+            break;
+        }
+
+        Traced { total: radiance, direct: radiance, emission, lobe }
+    }
+
+    /// Light the point lights send back along `ray` off `hit`, each one checked
+    /// for shadows with a ray of its own
+    fn point_lights(&self, ray: &Ray, hit: &HitRecord) -> Color {
+        let mut sum = color::BLACK;
+        for light in &self.lights {
+            let Some((to_light, distance, irradiance)) = light.towards(&hit.p) else {
+                continue;
             };
+            // Lights behind the real surface can't reach it, whatever the shading normal says
+            if to_light.dot(&hit.geometric_normal) <= 0.0 {
+                continue;
+            }
+            let lit = hit.lit(ray, &to_light);
+            if lit.is_near_zero() {
+                continue;
+            }
+
+            let epsilon = self.epsilon(&hit.p, hit.t * ray.direction.len());
+            let shadow = Ray::new(hit.p + epsilon * hit.geometric_normal, to_light);
+            if self.shoot_ray(&shadow, Interval::new(epsilon, distance - epsilon)).is_none() {
+                sum += lit * irradiance;
+            }
         }
 
-        color::BLACK    // If ray exhausts it's bounces, it lost all energy
+        sum
+    }
+
+    /// What rays escaping the world see
+    fn sky(&self, ray: &Ray) -> Color {
+        match &self.background {
+            Some(background) => background.value(&ray.direction),
+            None => Gradient::SKY.value(&ray.direction),
+        }
+    }
+
+    /// The ray scattered off `hit` ready to shoot, with the epsilon to start it at,
+    /// None if the normal policy doesn't let it leave that way
+    fn leave(&self, ray: &Ray, hit: &HitRecord, mut scattered: Ray) -> Option<(Ray, f64)> {
+        // Push the new ray's origin off the surface, to the side it's
+        // leaving through, so it can't hit the surface it starts on
+        let epsilon = self.epsilon(&hit.p, hit.t * ray.direction.len());
+        let leaving_geometry = scattered.direction.dot(&hit.geometric_normal) > 0.0;
+        let leaving_shading = scattered.direction.dot(&hit.normal) > 0.0;
+        if leaving_geometry != leaving_shading && self.normal_policy == NormalPolicy::Geometric {
+            return None;
+        }
+
+        let normal = match self.normal_policy {
+            NormalPolicy::Geometric => hit.geometric_normal,
+            NormalPolicy::Shading => hit.normal,
+        };
+        let side = match scattered.direction.dot(&normal) > 0.0 {
+            true => normal,
+            false => -normal
+        };
+        scattered.origin += epsilon * side;
+        scattered.channel = scattered.channel.or(ray.channel);
+
+        Some((scattered, epsilon))
     }
 }
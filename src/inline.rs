@@ -0,0 +1,105 @@
+//! One line object descriptions, for composing quick test scenes (and bug
+//! reports) on the command line:
+//!
+//! ```text
+//! sphere 0,1,0 r=1 mat=glass
+//! xz -1..1 -1..1 k=3 mat=light 4,4,4
+//! sphere 2,0.5,0 r=0.5 name=/probe mat=metal 0.9,0.9,0.9 0.1
+//! sphere 0,1,-2 r=1 mat=image earth.jpg
+//! ```
+//!
+//! Shapes are `sphere <center>`, with its radius in `r=`, or an axis aligned
+//! rectangle (`xy`, `xz` or `yz`) spanning two ranges at `k=` along the third
+//! axis. `back=` says what its back faces look like: shaded (the default),
+//! black or culled. `mat=` takes the rest of the line as a material (see
+//! `looks`), plain gray lambertian if missing.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::assets::AssetPaths;
+use crate::color::Color;
+use crate::hit::{BackFaces, HittableList, ObjectId};
+use crate::looks;
+use crate::material::{Lambertian, Material};
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::sphere::Sphere;
+
+/// The line with the file its material reads (if any) swapped for what `map`
+/// makes of it, see `looks::map_material_file`
+pub fn map_file(line: &str, map: impl FnOnce(&str) -> Result<String, String>) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some(start) = words.iter().position(|word| word.starts_with("mat=")) else {
+        return Ok(line.to_string());
+    };
+
+    let material = words[start..].join(" ");
+    let material = looks::map_material_file(&material["mat=".len()..], map)?;
+    Ok(format!("{} mat={}", words[..start].join(" "), material))
+}
+
+/// Parse `line` and add the object it describes to the world, named after its
+/// `name=` or "/added/<n>" (n counting the world's objects) otherwise. Files the
+/// material uses are found through `assets`.
+pub fn add(world: &mut HittableList, line: &str, assets: &AssetPaths) -> Result<ObjectId, String> {
+    let mut words = line.split_whitespace();
+    let shape = words.next().ok_or("missing shape")?;
+
+    let (mut positional, mut options) = (Vec::new(), Vec::new());
+    let mut material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    while let Some(word) = words.next() {
+        match word.split_once('=') {
+            // The material's own parameters have spaces, so it eats the rest of the line
+            Some(("mat", spec)) => {
+                let rest: Vec<&str> = std::iter::once(spec).chain(words.by_ref()).collect();
+                material = looks::parse_material(&rest.join(" "), assets)?;
+            },
+            Some((key, value)) => options.push((key, value)),
+            None => positional.push(word),
+        }
+    }
+
+    let option = |key: &str| options.iter().find(|(k, _)| *k == key).map(|(_, value)| *value);
+    let number = |key: &str| -> Result<f64, String> {
+        let value = option(key).ok_or_else(|| format!("{} needs {}=", shape, key))?;
+        value.parse().map_err(|_| format!("'{}' isn't a number", value))
+    };
+    let range = |idx: usize| -> Result<Range<f64>, String> {
+        let value = positional.get(idx).ok_or_else(|| format!("{} needs two ranges", shape))?;
+        let (start, end) = value.split_once("..").ok_or_else(|| format!("'{}' isn't a range like -1..1", value))?;
+        let bound = |bound: &str| bound.parse::<f64>().map_err(|_| format!("'{}' isn't a number", bound));
+        Ok(bound(start)?..bound(end)?)
+    };
+
+    let name = match option("name") {
+        Some(name) => name.to_string(),
+        None => format!("/added/{}", world.len()),
+    };
+    let back_faces = match option("back") {
+        None | Some("shaded") => BackFaces::Shaded,
+        Some("black") => BackFaces::Black,
+        Some("culled") => BackFaces::Culled,
+        Some(other) => return Err(format!("back={} isn't shaded, black or culled", other)),
+    };
+    let id = match shape {
+        "sphere" => {
+            let center = positional.first().ok_or("sphere needs a center")?;
+            let coords: Vec<f64> = center.split(',')
+                .map(|coord| coord.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("'{}' isn't a point like 0,1,0", center))?;
+            let [x, y, z] = coords[..] else {
+                return Err(format!("'{}' isn't a point like 0,1,0", center));
+            };
+            world.add_named(&name, Sphere::new(x, y, z, number("r")?, material))
+        },
+        "xy" => world.add_named(&name, XyRect::new(range(0)?, range(1)?, number("k")?, material)),
+        "xz" => world.add_named(&name, XzRect::new(range(0)?, range(1)?, number("k")?, material)),
+        "yz" => world.add_named(&name, YzRect::new(range(0)?, range(1)?, number("k")?, material)),
+        _ => return Err(format!("unknown shape '{}'", shape)),
+    };
+
+    world.set_back_faces(id, back_faces);
+
+    Ok(id)
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::Point3;
+
+/// A transformed reference to shared geometry. Many instances can point to the
+/// same object (often a BVH over a mesh's triangles), so placing a thousand
+/// trees costs a thousand transforms instead of a thousand copies of the tree.
+///
+/// Putting instances in a HittableList and building its BVH gives the classic
+/// two-level setup: a top-level tree over instances, each of them holding its
+/// own bottom-level tree over primitives in object space.
+pub struct Instance {
+    object: Arc<dyn Hit>,
+    transform: Transform,
+    bbox: Aabb
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hit>, transform: Transform) -> Self {
+        // World space box: the box around all 8 transformed corners of the object's box
+        let object_bbox = object.bounding_box();
+        let corners = (0..8).map(|corner| {
+            let pick = |axis: usize| match corner & (1 << axis) != 0 {
+                true => object_bbox.max[axis],
+                false => object_bbox.min[axis],
+            };
+            transform.point(&Point3::new(pick(0), pick(1), pick(2)))
+        });
+        let bbox = corners
+            .map(|corner| Aabb::new(corner, corner))
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+
+        Self { object, transform, bbox }
+    }
+}
+
+impl Hit for Instance {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        // The direction isn't normalized, so t means the same in both spaces
+        let local_ray = Ray::new(
+            self.transform.inverse_point(&ray.origin),
+            self.transform.inverse_vector(&ray.direction)
+        );
+
+        let mut hit = self.object.try_hit(&local_ray, interval)?;
+        hit.p = self.transform.point(&hit.p);
+        // The inverse transpose keeps which side the normal faces, so front_face still holds
+        hit.normal = self.transform.normal(&hit.normal).unit_vec();
+        hit.geometric_normal = self.transform.normal(&hit.geometric_normal).unit_vec();
+        hit.tangent = hit.tangent.map(|tangent| self.transform.vector(&tangent).unit_vec());
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -0,0 +1,56 @@
+use std::ops::Range;
+
+/// Closed interval of real numbers [min, max]. Unlike `Range<f64>` both bounds
+/// are part of the interval, and it comes with the bits of interval math
+/// ray/box intersections need.
+#[derive(Clone, Copy)]
+pub struct Interval {
+    pub min: f64,
+    pub max: f64
+}
+
+impl Interval {
+    /// Contains nothing - min > max, so it's the identity for `surrounding`
+    pub const EMPTY: Interval = Interval { min: f64::INFINITY, max: f64::NEG_INFINITY };
+    /// Contains everything
+    pub const UNIVERSE: Interval = Interval { min: f64::NEG_INFINITY, max: f64::INFINITY };
+
+    pub fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest interval containing both intervals
+    pub fn surrounding(&self, other: &Interval) -> Interval {
+        Interval::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    /// min <= x <= max
+    pub fn contains(&self, x: f64) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    /// min < x < max, bounds excluded
+    pub fn surrounds(&self, x: f64) -> bool {
+        self.min < x && x < self.max
+    }
+
+    pub fn clamp(&self, x: f64) -> f64 {
+        x.clamp(self.min, self.max)
+    }
+
+    /// Grow the interval by `delta` in total, half on each side
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
+}
+
+impl From<Range<f64>> for Interval {
+    fn from(range: Range<f64>) -> Self {
+        Interval::new(range.start, range.end)
+    }
+}
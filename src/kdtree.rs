@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+/// Nodes with this many objects (or fewer) become leaves
+const LEAF_SIZE: usize = 4;
+
+/// Kd-tree: space (not objects, like the BVH) is split in two by an axis-aligned
+/// plane at every node. Objects crossing the plane end up on both sides, in
+/// exchange rays can walk the cells front to back and stop at the first cell
+/// with a hit.
+pub struct KdTree {
+    objects: Vec<Arc<dyn Hit>>,
+    bbox: Aabb,
+    root: KdNode
+}
+
+enum KdNode {
+    /// Indices into `KdTree::objects`
+    Leaf(Vec<usize>),
+    Split { axis: usize, position: f64, below: Box<KdNode>, above: Box<KdNode> }
+}
+
+impl KdTree {
+    /// Build a tree over the objects. Panics if there are none.
+    pub fn new(objects: Vec<Arc<dyn Hit>>) -> Self {
+        assert!(!objects.is_empty(), "Can't build a kd-tree without objects");
+
+        let boxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let bbox = boxes.iter().copied().reduce(|a, b| a.surrounding(&b)).unwrap();
+
+        // Usual depth limit heuristic, straddling objects make deeper trees pointless
+        let max_depth = 8 + (1.3 * (objects.len() as f64).log2()) as usize;
+        let root = KdTree::build(&boxes, (0..objects.len()).collect(), bbox, max_depth);
+
+        Self { objects, bbox, root }
+    }
+
+    /// Split the cell at the median centroid of its objects along the cell's widest axis
+    fn build(boxes: &[Aabb], indices: Vec<usize>, cell: Aabb, depth: usize) -> KdNode {
+        if indices.len() <= LEAF_SIZE || depth == 0 {
+            return KdNode::Leaf(indices);
+        }
+
+        let axis = cell.longest_axis();
+        let mut centroids: Vec<f64> = indices.iter().map(|&idx| boxes[idx].centroid()[axis]).collect();
+        let mid = centroids.len() / 2;
+        let (_, &mut position, _) = centroids.select_nth_unstable_by(mid, f64::total_cmp);
+
+        let below: Vec<usize> = indices.iter().copied().filter(|&idx| boxes[idx].min[axis] <= position).collect();
+        let above: Vec<usize> = indices.iter().copied().filter(|&idx| boxes[idx].max[axis] >= position).collect();
+
+        // Splitting is useless if (almost) everything straddles the plane
+        if below.len() == indices.len() && above.len() == indices.len() {
+            return KdNode::Leaf(indices);
+        }
+
+        let (mut below_cell, mut above_cell) = (cell, cell);
+        match axis {
+            0 => { below_cell.max.x = position; above_cell.min.x = position; },
+            1 => { below_cell.max.y = position; above_cell.min.y = position; },
+            _ => { below_cell.max.z = position; above_cell.min.z = position; },
+        }
+
+        KdNode::Split {
+            axis,
+            position,
+            below: Box::new(KdTree::build(boxes, below, below_cell, depth - 1)),
+            above: Box::new(KdTree::build(boxes, above, above_cell, depth - 1)),
+        }
+    }
+
+    /// Find the closest hit inside `interval`, where `cell` is the part of the
+    /// ray (in t) that's inside the node's cell.
+    fn traverse(&self, node: &KdNode, ray: &Ray, cell: Interval, interval: &Interval) -> Option<HitRecord> {
+        match node {
+            KdNode::Leaf(indices) => {
+                let mut interval = *interval;
+                let mut closest = None;
+                for &idx in indices {
+                    if let Some(hit) = self.objects[idx].try_hit(ray, &interval) {
+                        interval.max = hit.t;
+                        closest = Some(hit);
+                    }
+                }
+
+                closest
+            },
+            KdNode::Split { axis, position, below, above } => {
+                let (origin, direction) = (ray.origin[*axis], ray.direction[*axis]);
+                let t_split = (position - origin) / direction;
+
+                // The child on the origin's side is crossed first
+                let (near, far) = match origin < *position || (origin == *position && direction <= 0.0) {
+                    true => (below, above),
+                    false => (above, below),
+                };
+
+                if t_split > cell.max || t_split <= 0.0 {
+                    return self.traverse(near, ray, cell, interval);
+                }
+                if t_split < cell.min {
+                    return self.traverse(far, ray, cell, interval);
+                }
+
+                let near_hit = self.traverse(near, ray, Interval::new(cell.min, t_split), interval);
+                match near_hit {
+                    // Hits inside the near cell are in front of anything the far cell holds
+                    Some(hit) if hit.t <= t_split => Some(hit),
+                    // Objects straddling the plane may have been hit past it, so the
+                    // far side can still have something closer
+                    near_hit => {
+                        let far_interval = match &near_hit {
+                            Some(hit) => Interval::new(interval.min, hit.t),
+                            None => *interval,
+                        };
+                        let far_cell = Interval::new(t_split, cell.max);
+                        self.traverse(far, ray, far_cell, &far_interval).or(near_hit)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Hit for KdTree {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let cell = self.bbox.clip(ray, interval)?;
+        self.traverse(&self.root, ray, cell, interval)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
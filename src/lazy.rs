@@ -0,0 +1,58 @@
+use std::sync::{Arc, OnceLock};
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhBuilder, BvhNode};
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+type Generator = Box<dyn Fn() -> Vec<Arc<dyn Hit>> + Send + Sync>;
+
+/// Geometry generated the first time a ray reaches its bounding box (tessellating
+/// a surface, scattering a forest's instances, ...), so a huge procedural world
+/// only pays the time and memory for the parts the camera's rays actually get to.
+/// Once generated it stays, in its own BVH.
+pub struct LazyHittable {
+    bbox: Aabb,
+    generate: Generator,
+    /// None when the generator came up with nothing
+    geometry: OnceLock<Option<BvhNode>>
+}
+
+impl LazyHittable {
+    /// `bbox` has to enclose everything `generate` makes, it's all acceleration
+    /// structures have to go on until then. `generate` runs in the middle of a
+    /// render on one of rayon's threads, so it mustn't use rayon itself (see
+    /// `BvhBuilder::sequential`).
+    pub fn new(bbox: Aabb, generate: impl Fn() -> Vec<Arc<dyn Hit>> + Send + Sync + 'static) -> Self {
+        Self { bbox, generate: Box::new(generate), geometry: OnceLock::new() }
+    }
+
+    pub fn is_generated(&self) -> bool {
+        self.geometry.get().is_some()
+    }
+}
+
+impl Hit for LazyHittable {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, interval) {
+            return None;
+        }
+
+        // Other threads getting here meanwhile wait for the first one to generate it.
+        // Sequentially: a parallel build could have this thread steal a pixel that
+        // gets here too, and wait on its own initialization.
+        let geometry = self.geometry.get_or_init(|| {
+            let objects = (self.generate)();
+            match objects.is_empty() {
+                true => None,
+                false => Some(BvhBuilder::new().sequential(true).build(objects)),
+            }
+        });
+        geometry.as_ref()?.try_hit(ray, interval)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -0,0 +1,49 @@
+pub mod vec3;
+pub mod color;
+pub mod ray;
+pub mod interval;
+pub mod aabb;
+pub mod hit;
+pub mod stats;
+pub mod bvh;
+pub mod kdtree;
+pub mod grid;
+pub mod transform;
+pub mod instance;
+pub mod lazy;
+pub mod sphere;
+pub mod background;
+pub mod light;
+pub mod camera;
+pub mod cubemap;
+pub mod environment;
+pub mod aperture;
+pub mod material;
+pub mod texture;
+pub mod perlin;
+pub mod worley;
+pub mod onb;
+pub mod microfacet;
+pub mod sampler;
+pub mod principled;
+pub mod rect;
+pub mod mesh;
+pub mod pointcloud;
+pub mod scenes;
+pub mod looks;
+pub mod inline;
+pub mod film;
+pub mod render;
+pub mod control;
+pub mod post;
+pub mod grade;
+pub mod overlay;
+pub mod metrics;
+pub mod bisect;
+pub mod batch;
+pub mod config;
+pub mod bundle;
+pub mod assets;
+pub mod error;
+#[cfg(feature = "monitor")]
+pub mod monitor;
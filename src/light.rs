@@ -0,0 +1,57 @@
+//! Lights too small to be found by bouncing rays around: instead of waiting for a
+//! path to run into them, every surface a path hits sends a shadow ray towards
+//! each of them and adds their light if nothing's in the way. Described on the
+//! command line by a position and an intensity:
+//!
+//! ```text
+//! 0,3,0 20
+//! -2,4,1 30,25,20
+//! ```
+
+use crate::color::Color;
+use crate::vec3::{Point3, Vec3};
+
+/// Light given off equally in every direction from a single point. Its intensity
+/// is the light per solid angle, so a surface `d` away facing it gets intensity / d².
+#[derive(Clone)]
+pub struct PointLight {
+    position: Point3,
+    intensity: Color
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self { position, intensity }
+    }
+
+    /// Direction from `p` towards the light (unit length), how far it is and the
+    /// light a surface facing it gets there, shadows aside. None right on the
+    /// light, where there's no direction to it.
+    pub fn towards(&self, p: &Point3) -> Option<(Vec3, f64, Color)> {
+        let offset = self.position - *p;
+        let distance = offset.len();
+        match distance > 0.0 {
+            true => Some((offset / distance, distance, self.intensity / (distance * distance))),
+            false => None,
+        }
+    }
+}
+
+/// A point light from "<x,y,z> <intensity>", the intensity a color written as
+/// "r,g,b" or a single gray value
+pub fn parse(spec: &str) -> Result<PointLight, String> {
+    let words: Vec<&str> = spec.split_whitespace().collect();
+    let [position, intensity] = words[..] else {
+        return Err("expected '<x,y,z> <intensity>'".to_string());
+    };
+
+    let coords: Vec<f64> = position.split(',')
+        .map(|coord| coord.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("'{}' isn't a point like 0,1,0", position))?;
+    let [x, y, z] = coords[..] else {
+        return Err(format!("'{}' isn't a point like 0,1,0", position));
+    };
+
+    Ok(PointLight::new(Point3::new(x, y, z), crate::looks::parse_color(intensity)?))
+}
@@ -0,0 +1,308 @@
+//! Look variations: rules swapping the material of every object whose name
+//! matches a glob, so an all-gold or all-glass version of a scene can be
+//! rendered without touching the code that builds it.
+//!
+//! A rules file has one rule per line, `<glob> = <material>`, and '#' comments:
+//!
+//! ```text
+//! /big_spheres/*  = gold
+//! /small_spheres/** = glass 1.5
+//! /ground = lambertian 0.2,0.2,0.25
+//! ```
+//!
+//! In globs `*` matches anything but a '/', `**` anything at all and `?` any
+//! single character. When several rules match an object the last one wins.
+
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::assets::AssetPaths;
+use crate::color::Color;
+use crate::hit::{Hit, HitRecord, HittableList};
+use crate::interval::Interval;
+use crate::material::{BlinnPhong, Dielectric, DiffuseLight, Lambertian, Material, Metal, Mirror, Pbr, Plastic, Sheen};
+use crate::ray::Ray;
+use crate::texture::{CellPattern, Cellular, Checker, Filter, ImageTexture, Marble, NoiseTexture, Ramp, RampInput, Texture, UvTransform};
+
+/// Geometry drawn with another material than the one it was built with
+pub struct MaterialOverride {
+    geometry: Arc<dyn Hit>,
+    material: Arc<dyn Material>
+}
+
+impl MaterialOverride {
+    pub fn new(geometry: Arc<dyn Hit>, material: Arc<dyn Material>) -> Self {
+        Self { geometry, material }
+    }
+}
+
+impl Hit for MaterialOverride {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let mut hit = self.geometry.try_hit(ray, interval)?;
+        hit.material = self.material.clone();
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.geometry.bounding_box()
+    }
+}
+
+/// Objects matching `pattern` get `material`
+pub struct Rule {
+    pub pattern: String,
+    pub material: Arc<dyn Material>
+}
+
+/// Parse a rules file's contents, errors point at the offending line. Files the
+/// materials use (image textures) are found through `assets`.
+pub fn parse_rules(text: &str, assets: &AssetPaths) -> Result<Vec<Rule>, String> {
+    let mut rules = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (pattern, material) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected '<glob> = <material>'", idx + 1))?;
+        let material = parse_material(material, assets).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        rules.push(Rule { pattern: pattern.trim().to_string(), material });
+    }
+
+    Ok(rules)
+}
+
+/// Swap the material of every named object matching a rule, returning how many
+/// objects changed. Run it before building the world's accelerator.
+pub fn apply(world: &mut HittableList, rules: &[Rule]) -> usize {
+    let matches: Vec<_> = world.iter()
+        .filter_map(|(id, geometry)| {
+            let name = world.name(id)?;
+            let rule = rules.iter().rev().find(|rule| glob_match(&rule.pattern, name))?;
+            Some((id, MaterialOverride::new(geometry.clone(), rule.material.clone())))
+        })
+        .collect();
+
+    let changed = matches.len();
+    for (id, geometry) in matches {
+        world.replace(id, geometry);
+    }
+
+    changed
+}
+
+/// A material from a short description: a name followed by its parameters,
+/// colors written as "r,g,b". Presets (gold, silver, copper, chrome, glass,
+/// water, diamond, white, black, clay) need no parameters. Image files are found
+/// through `assets`.
+pub fn parse_material(spec: &str, assets: &AssetPaths) -> Result<Arc<dyn Material>, String> {
+    let mut words = spec.split_whitespace();
+    let kind = words.next().ok_or("missing material")?;
+    let params: Vec<&str> = words.collect();
+
+    let color = |idx: usize, default: Color| match params.get(idx) {
+        Some(value) => parse_color(value),
+        None => Ok(default),
+    };
+    let number = |idx: usize, default: f64| match params.get(idx) {
+        Some(value) => value.parse().map_err(|_| format!("'{}' isn't a number", value)),
+        None => Ok(default),
+    };
+
+    let material: Arc<dyn Material> = match kind {
+        "checker" => {
+            // "checker <color> <color> <cube size>", the book's checkered ground by default
+            let checker = Checker::solid(number(2, 0.32)?, color(0, Color::new(0.2, 0.3, 0.1))?, color(1, Color::new(0.9, 0.9, 0.9))?);
+            Arc::new(Lambertian::textured(Arc::new(checker)))
+        },
+        "noise" => {
+            // "noise <color> <frequency>"
+            let noise = NoiseTexture::new(number(1, 4.0)?).with_color(color(0, crate::color::WHITE)?);
+            Arc::new(Lambertian::textured(Arc::new(noise)))
+        },
+        "marble" => {
+            // "marble <color> <frequency>"
+            let marble = Marble::new(number(1, 4.0)?).with_color(color(0, crate::color::WHITE)?);
+            Arc::new(Lambertian::textured(Arc::new(marble)))
+        },
+        "cells" | "cracks" => {
+            // "cells <color> <frequency>", Worley noise
+            let pattern = match kind {
+                "cells" => CellPattern::Cells,
+                _ => CellPattern::Cracks,
+            };
+            let cellular = Cellular::new(pattern, number(1, 4.0)?).with_color(color(0, crate::color::WHITE)?);
+            Arc::new(Lambertian::textured(Arc::new(cellular)))
+        },
+        "image" => {
+            // "image <path> [filter=nearest|bilinear] [placement]", mapped by the surface's uv
+            let (path, options) = params.split_first().ok_or("image needs a file")?;
+            let (filter, placement): (Vec<&str>, Vec<&str>) = options.iter().partition(|option| option.starts_with("filter="));
+            let filter = match filter.last().map(|filter| &filter["filter=".len()..]) {
+                None | Some("bilinear") => Filter::Bilinear,
+                Some("nearest") => Filter::Nearest,
+                Some(other) => return Err(format!("filter={} isn't nearest or bilinear", other)),
+            };
+            let image = ImageTexture::open(&assets.resolve(path)?)?.with_filter(filter);
+            Arc::new(Lambertian::textured(placed(Arc::new(image), &placement)?))
+        },
+        "ramp" => {
+            // "ramp <u|v|axis> <position>:<color> ... [placement]"
+            let (placement, ramp): (Vec<&str>, Vec<&str>) = params.iter().partition(|param| param.contains('='));
+            Arc::new(Lambertian::textured(placed(Arc::new(parse_ramp(&ramp)?), &placement)?))
+        },
+        "lambertian" | "diffuse" => Arc::new(Lambertian::new(color(0, Color::new(0.5, 0.5, 0.5))?)),
+        "metal" => Arc::new(Metal::new(color(0, Color::new(0.7, 0.7, 0.7))?, number(1, 0.0)?)),
+        "glass" | "dielectric" => {
+            // Optionally tinted: "glass <ior> <tint> <distance the tint is reached at>"
+            let glass = Dielectric::new(number(0, 1.5)?);
+            match params.get(1) {
+                Some(_) => Arc::new(glass.with_absorption(color(1, crate::color::WHITE)?, number(2, 1.0)?)),
+                None => Arc::new(glass),
+            }
+        },
+        "dispersive" | "prism" => Arc::new(Dielectric::new(number(0, 1.5)?).with_dispersion(number(1, 30.0)?)),
+        "tinted-glass" => Arc::new(Dielectric::new(number(1, 1.5)?).with_tint(color(0, Color::new(0.6, 0.9, 0.7))?)),
+        "phong" => Arc::new(BlinnPhong::new(color(0, Color::new(0.5, 0.5, 0.5))?, color(1, Color::new(0.3, 0.3, 0.3))?, number(2, 32.0)?)),
+        "plastic" => Arc::new(Plastic::new(color(0, Color::new(0.5, 0.5, 0.5))?, number(1, 0.1)?).with_ior(number(2, 1.5)?)),
+        "sheen" | "velvet" => Arc::new(Sheen::new(color(0, Color::new(0.3, 0.05, 0.1))?, color(1, Color::new(0.6, 0.6, 0.6))?, number(2, 0.5)?)),
+        "mirror" => Arc::new(Mirror::tinted(color(0, crate::color::WHITE)?)),
+        "light" => Arc::new(DiffuseLight::new(color(0, Color::new(4.0, 4.0, 4.0))?).with_strength(number(1, 1.0)?)),
+        "pbr" => Arc::new(Pbr::new(color(0, Color::new(0.5, 0.5, 0.5))?, number(1, 0.0)?, number(2, 0.5)?)),
+        "gold" => Arc::new(Metal::new(Color::new(1.0, 0.78, 0.34), 0.05)),
+        "silver" => Arc::new(Metal::new(Color::new(0.97, 0.96, 0.91), 0.05)),
+        "copper" => Arc::new(Metal::new(Color::new(0.95, 0.64, 0.54), 0.1)),
+        "chrome" => Arc::new(Metal::new(Color::new(0.55, 0.56, 0.55), 0.0)),
+        "water" => Arc::new(Dielectric::new(1.33)),
+        "diamond" => Arc::new(Dielectric::new(2.42).with_dispersion(55.0)),
+        "white" => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        "black" => Arc::new(Lambertian::new(Color::new(0.04, 0.04, 0.04))),
+        "clay" => Arc::new(Lambertian::new(Color::new(0.7, 0.45, 0.35))),
+        _ => return Err(format!("unknown material '{}'", kind)),
+    };
+
+    Ok(material)
+}
+
+/// The material description with the file it reads (an image texture's, if any)
+/// swapped for what `map` makes of it, for moving scenes around (see bundle.rs)
+pub fn map_material_file(spec: &str, map: impl FnOnce(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut words: Vec<String> = spec.split_whitespace().map(str::to_string).collect();
+    match words.as_slice() {
+        [kind, file, ..] if kind == "image" => words[1] = map(file)?,
+        _ => return Ok(spec.to_string()),
+    }
+
+    Ok(words.join(" "))
+}
+
+/// A rules file's contents with the files its materials read swapped like
+/// `map_material_file` does. Comments on those rules' lines are dropped.
+pub fn map_rule_files(text: &str, mut map: impl FnMut(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut mapped = String::new();
+    for (idx, line) in text.lines().enumerate() {
+        let rule = line.split('#').next().unwrap_or("");
+        match rule.split_once('=') {
+            Some((pattern, material)) => {
+                let material = map_material_file(material, &mut map).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+                mapped += &format!("{} = {}", pattern.trim(), material);
+            },
+            None => mapped += line,
+        }
+        mapped.push('\n');
+    }
+
+    Ok(mapped)
+}
+
+/// `texture` moved around by "scale=<u>,<v>", "offset=<u>,<v>" and "rotate=<degrees>"
+/// (see `UvTransform`), as it is without any. A single number scales or offsets both.
+fn placed(texture: Arc<dyn Texture>, placement: &[&str]) -> Result<Arc<dyn Texture>, String> {
+    if placement.is_empty() {
+        return Ok(texture);
+    }
+
+    let pair = |value: &str| -> Result<(f64, f64), String> {
+        let number = |number: &str| number.parse::<f64>().map_err(|_| format!("'{}' isn't a number", number));
+        match value.split_once(',') {
+            Some((u, v)) => Ok((number(u)?, number(v)?)),
+            None => number(value).map(|both| (both, both)),
+        }
+    };
+
+    let mut transform = UvTransform::new(texture);
+    for param in placement {
+        transform = match param.split_once('=') {
+            Some(("scale", value)) => pair(value).map(|(u, v)| transform.with_scale(u, v))?,
+            Some(("offset", value)) => pair(value).map(|(u, v)| transform.with_offset(u, v))?,
+            Some(("rotate", value)) => {
+                let degrees = value.parse().map_err(|_| format!("'{}' isn't an angle", value))?;
+                transform.with_rotation(degrees)
+            },
+            _ => return Err(format!("'{}' isn't scale=, offset= or rotate=", param)),
+        };
+    }
+
+    Ok(Arc::new(transform))
+}
+
+/// A ramp from "<input> <position>:<color> ...", the input being u, v or an axis
+/// written as "x,y,z"
+pub fn parse_ramp(params: &[&str]) -> Result<Ramp, String> {
+    let (input, stops) = params.split_first().ok_or("ramp needs an input (u, v or an axis) and stops")?;
+    let input = match *input {
+        "u" => RampInput::U,
+        "v" => RampInput::V,
+        axis => {
+            let axis = parse_color(axis).map_err(|_| format!("'{}' isn't u, v or an axis like 0,1,0", axis))?;
+            RampInput::Axis(axis)
+        },
+    };
+
+    let stops = stops.iter()
+        .map(|stop| {
+            let (position, color) = stop.split_once(':').ok_or_else(|| format!("'{}' isn't a stop like 0.5:1,0,0", stop))?;
+            let position = position.parse().map_err(|_| format!("'{}' isn't a number", position))?;
+            Ok((position, parse_color(color)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    match stops.is_empty() {
+        true => Err("ramp needs at least one stop".to_string()),
+        false => Ok(Ramp::new(input, stops)),
+    }
+}
+
+/// "r,g,b", or a single number for a gray
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let channels: Vec<f64> = value.split(',')
+        .map(|channel| channel.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("'{}' isn't a color", value))?;
+
+    match channels[..] {
+        [gray] => Ok(Color::new(gray, gray, gray)),
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err(format!("'{}' isn't a color, expected r,g,b", value)),
+    }
+}
+
+/// Whether a '/' separated name matches the glob
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    matches(&pattern, &name)
+}
+
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        ['*', '*', rest @ ..] => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        ['*', rest @ ..] => {
+            // Anything up to the end of this path segment
+            let segment = name.iter().position(|&c| c == '/').unwrap_or(name.len());
+            (0..=segment).any(|skip| matches(rest, &name[skip..]))
+        },
+        ['?', rest @ ..] => !name.is_empty() && matches(rest, &name[1..]),
+        [c, rest @ ..] => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
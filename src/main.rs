@@ -1,141 +1,752 @@
-mod vec3;
-mod color;
-mod ray;
-mod hit;
-mod sphere;
-mod camera;
-mod material;
-
-use camera::Camera;
-use color::Color;
-use hit::HittableList;
-use material::{Dielectric, Lambertian, Metal};
-use sphere::Sphere;
-use vec3::{Point3, Vec3};
+use raytracer_in_a_weekend::aperture::Aperture;
+use raytracer_in_a_weekend::assets::AssetPaths;
+use raytracer_in_a_weekend::camera::{Camera, LensSampling};
+use raytracer_in_a_weekend::color::{self, Color};
+use raytracer_in_a_weekend::config::Resolution;
+use raytracer_in_a_weekend::control::RenderControl;
+use raytracer_in_a_weekend::cubemap::CubeLayout;
+use raytracer_in_a_weekend::environment::EnvironmentMap;
+use raytracer_in_a_weekend::error::Error;
+use raytracer_in_a_weekend::film::{Film, PostProcess, Region};
+use raytracer_in_a_weekend::grade::Grade;
+use raytracer_in_a_weekend::metrics::Variance;
+use raytracer_in_a_weekend::post::Bloom;
+use raytracer_in_a_weekend::render::{Integrator, Pass, Renderer, Rendered};
+use raytracer_in_a_weekend::scenes::Scene;
+use raytracer_in_a_weekend::{background, batch, bisect, bundle, config, inline, light, looks, metrics, overlay, scenes, stats};
+#[cfg(feature = "monitor")]
+use raytracer_in_a_weekend::monitor;
 
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 
 use image::RgbImage;
-use rayon::iter::{ParallelBridge, ParallelIterator};
 
-// Image constants
-pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMG_WIDTH: f64 = 1280.0;
-const IMG_HEIGHT: f64 = IMG_WIDTH / ASPECT_RATIO;
+// Render constants, the image's size comes from --resolution (720p by default)
 const PIXEL_SAMPLES: f64 = 500.0;
-const MAX_RAY_BOUNCES: u32 = 50;
+const SEED: u64 = 0x5eed;
 
 fn main() {
-    // World/Scene initialization
-    let world = random_scene();
-
-    // Instantiate Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-
-    let cam = Camera::new(lookfrom, lookat, vup, 20.0, ASPECT_RATIO, aperture, dist_to_focus);
-    
-    // Setup our PNG RgbImage and get an iterator to its rows
-    let mut img = RgbImage::new(IMG_WIDTH as u32, IMG_HEIGHT as u32);
-    let mut rows = img.rows_mut();
-
-    // Rendering main loop - iterate over rows and pixels then shoot our rays
-    for j in (0..IMG_HEIGHT as usize).rev() {
-        eprintln!("On row {}", j);
-        let row = rows.next().unwrap().enumerate();
-
-        row.par_bridge().for_each(|(i, img_pixel)| {
-            let mut pixel = color::BLACK;
-
-            let rng = fastrand::Rng::new();
-            for _ in 0..PIXEL_SAMPLES as usize {
-                let u = (i as f64 + rng.f64()) / (IMG_WIDTH - 1.0);
-                let v = (j as f64 + rng.f64()) / (IMG_HEIGHT - 1.0);
-                
-                let ray = cam.gen_ray(u, v);
-                pixel += world.find_ray_color(ray, MAX_RAY_BOUNCES);
+    // Exit codes tell scripts what kind of failure it was, see error.rs
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
+    // Options start with "--" and can go anywhere, everything else is positional:
+    // the output image's name, then which scene to render
+    let args = join_values(std::env::args().skip(1).collect());
+    let (cli, mut positional): (Vec<&str>, Vec<&str>) = args.iter()
+        .map(String::as_str)
+        .partition(|arg| arg.starts_with("--"));
+
+    // A bundle given as the scene brings the actual scene's name and its options
+    let bundle = match (positional.first(), positional.get(1)) {
+        (Some(&"compare" | &"pack" | &"batch"), _) => None,
+        (_, Some(path)) if path.ends_with(".tar") => Some(bundle::unpack(path)?),
+        _ => None,
+    };
+    if let Some(bundle) = &bundle {
+        positional[1] = &bundle.scene;
+    }
+
+    // Options from the command line win over the bundle's, then the environment's and
+    // last the config file's
+    let bundled = bundle.as_ref().map(|bundle| bundle.options.clone()).unwrap_or_default();
+    let options = layered_options(&cli, bundled)?;
+    let flags: Vec<&str> = options.iter().map(String::as_str).collect();
+
+    // "pack <bundle.tar> <scene> [options]" saves the scene and the command line's
+    // options as a bundle
+    if positional.first() == Some(&"pack") {
+        let [_, path, scene, ..] = positional[..] else {
+            return Err(Error::Usage("usage: pack <bundle.tar> <scene> [options]".to_string()));
+        };
+        return bundle::pack(path, scene, &cli, &assets(&flags));
+    }
+
+    // "compare <a.png> <b.png> [map name]" compares two images instead of rendering
+    if positional.first() == Some(&"compare") {
+        return compare(&positional[1..], &flags);
+    }
+
+    // Renders can be steered while they run (pause, resume, threads <n>, cancel)
+    let session = start_session(&flags)?;
+
+    // "bisect-scene <scene> <predicate>" shrinks a scene showing a bug
+    if positional.first() == Some(&"bisect-scene") {
+        return bisect_scene(&positional[1..], &flags, &session);
+    }
+
+    // "batch <manifest>" renders every job listed in it, see batch.rs
+    if positional.first() == Some(&"batch") {
+        return batch(&positional[1..], &flags, &session);
+    }
+
+    let name = positional.first().copied().unwrap_or("ray");
+    let scene_name = positional.get(1).copied().unwrap_or("random");
+    render(name, scene_name, &flags, &session, &mut None)
+}
+
+/// Render `scene_name` into images named `name`, with the cached scene if it's the
+/// one asked for, then leave this one in the cache
+fn render(name: &str, scene_name: &str, flags: &[&str], session: &Session, cache: &mut Option<SceneCache>) -> Result<(), Error> {
+    // World/Scene initialization, its camera gets the image's aspect ratio
+    let resolution = resolution(flags)?;
+    let key = scene_key(scene_name, resolution.aspect_ratio(), flags);
+    let (mut scene, camera) = match cache.take() {
+        Some(SceneCache { key: cached, mut scene, camera }) if cached == key => {
+            eprintln!("Reusing the scene of the previous render");
+            scene.camera = camera.clone();
+            (scene, camera)
+        },
+        _ => {
+            let mut scene = load_scene(scene_name, resolution.aspect_ratio(), flags)?;
+            scene.world.build_bvh();
+            let camera = scene.camera.clone();
+            (scene, camera)
+        },
+    };
+
+    // --aperture=A and --focus-dist=D change the camera's depth of field
+    let aperture = parse_flag(flags, "--aperture", "a number")?;
+    let focus_dist = parse_flag(flags, "--focus-dist", "a number")?;
+    if focus_dist.is_some_and(|dist: f64| dist <= 0.0) {
+        return Err(Error::Usage("--focus-dist must be positive".to_string()));
+    }
+    if aperture.is_some() || focus_dist.is_some() {
+        let (current_aperture, current_focus) = scene.camera.lens();
+        scene.camera = scene.camera.with_lens(aperture.unwrap_or(current_aperture), focus_dist.unwrap_or(current_focus));
+    }
+    // --lens-sampling=random|concentric|spiral spreads the samples over the lens
+    let lens_sampling = match flag_value(flags, "--lens-sampling") {
+        None | Some("random") => LensSampling::Random,
+        Some("concentric") => LensSampling::Concentric,
+        Some("spiral") => LensSampling::Spiral,
+        Some(other) => return Err(Error::Usage(format!("--lens-sampling takes random, concentric or spiral, not '{}'", other))),
+    };
+    scene.camera = scene.camera.with_lens_sampling(lens_sampling);
+    // --aperture-image=<mask> shapes the lens opening, and so the bokeh
+    if let Some(path) = flag_value(flags, "--aperture-image") {
+        let path = assets(flags).resolve(path).map_err(Error::Scene)?.display().to_string();
+        let mask = image::open(&path).map_err(|source| Error::Image { path: path.clone(), source })?;
+        let aperture = Aperture::from_image(&mask.to_luma8()).map_err(|e| Error::Usage(format!("--aperture-image '{}': {}", path, e)))?;
+        scene.camera = scene.camera.with_aperture_shape(Arc::new(aperture));
+    }
+
+    // --downscale=N renders N times bigger and averages back down when saving
+    let downscale = parse_count(flags, "--downscale")?.unwrap_or(1);
+    let (width, height) = (resolution.width * downscale, resolution.height * downscale);
+    check_memory(width, height)?;
+
+    let name = output_name(flags, name)?;
+    let grade = grade(flags)?;
+    let name = name.as_str();
+    let renderer = configure_renderer(flags, width, height, session)?;
+    let start = Instant::now();
+    let dof_overlay = switch(flags, "--dof-overlay");
+    let focus_stack = parse_flag(flags, "--focus-stack", "a whole number")?;
+    let cube_map = match flag_value(flags, "--cube-map") {
+        None => None,
+        Some("cross") => Some(CubeLayout::Cross),
+        Some("equirect") => Some(CubeLayout::Equirect),
+        Some(other) => return Err(Error::Usage(format!("--cube-map takes cross or equirect, not '{}'", other))),
+    };
+    if cube_map.is_some() && (flag_value(flags, "--crop").is_some() || !b_side(flags).is_empty()) {
+        return Err(Error::Usage("--cube-map can't be combined with --crop or --b- options".to_string()));
+    }
+    let variance_runs = parse_flag(flags, "--variance", "a whole number")?;
+    let Rendered { film, heat, passes, .. } = match (variance_runs, cube_map, focus_stack, switch(flags, "--preview") || dof_overlay) {
+        // --variance=K renders K times with different seeds and keeps the mean
+        (Some(runs), ..) => variance_study(&scene, flags, (width, height), session, runs, name)?,
+        // --cube-map=cross|equirect renders all around the camera, i.e. to light other scenes
+        (None, Some(layout), ..) => renderer.render_cube_map(&scene, layout),
+        // --focus-stack=N merges N renders focused from near to far, all in focus
+        (None, None, Some(shots), _) => renderer.render_focus_stack(&scene, shots),
+        // Coarse to fine previews, overwriting <name>_preview.png as they get better
+        (None, None, None, true) => renderer.render_progressive(&scene, |preview| {
+            let mut preview = preview.downscaled(downscale).to_image();
+            if dof_overlay {
+                overlay::depth_of_field(&mut preview, &scene);
             }
+            if let Err(e) = save(&preview, &format!("{}_preview", name)) {
+                eprintln!("Unable to save the preview - {}", e);
+            }
+        }),
+        (None, None, None, false) => renderer.render(&scene),
+    };
+    let elapsed = start.elapsed();
+    if session.control.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
 
-            *img_pixel = pixel.to_rgb(PIXEL_SAMPLES as f64);
-        })
+    let (rendered_width, rendered_height) = (film.width(), film.height());
+    let mut film = film.downscaled(downscale);
+    // Environment maps need the light's full range, so cube maps are saved in EXR too
+    if cube_map.is_some() {
+        save_hdr(&film, name)?;
     }
 
-    let path = std::env::args().nth(1).unwrap_or("ray".to_string());
-    let path = format!("/home/cypherlock/images/{}.png", path);
-    eprintln!("Saving image to path {}", path);
-    if let Err(e) = img.save(path) {
-        eprintln!("Error on saving image - {}", e);
-        eprintln!("Defaulting to 'ray.png'");
+    // A/B mode: "--b-<option>" flags override options for a second render, saved
+    // split screen with the first one (A on the left), plus a map of where they differ
+    let b_flags = b_side(flags);
+    if !b_flags.is_empty() {
+        let b_flags: Vec<&str> = b_flags.iter().map(String::as_str).collect();
+        let b_film = configure_renderer(&b_flags, width, height, session)?.render(&scene).film.downscaled(downscale);
+        if session.control.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        report(&film, &b_film);
+        save(&metrics::flip_map(&film, &b_film), &format!("{}_diff", name))?;
+        film = film.split_with(&b_film);
+    }
+
+    if let Some(sharpness) = parse_flag(flags, "--sharpen", "a number in 0.0..=1.0")? {
+        film = film.sharpened(sharpness);
+    }
+    if let Some(grade) = &grade {
+        film = grade.graded(&film);
+    }
+
+    let mut img = film.to_image();
+    if switch(flags, "--burn-in") {
+        // Dailies style slate, --frame=N labels frames of a sequence rendered by a script
+        let mut slate = format!(
+            "{} | {}X{} | {} SPP | {:.1}S",
+            scene_name, img.width(), img.height(), renderer.samples(), elapsed.as_secs_f64()
+        );
+        if let Some(frame) = flag_value(flags, "--frame") {
+            slate += &format!(" | FRAME {}", frame);
+        }
+        overlay::burn_in(&mut img, &slate);
+    }
+    save(&img, name)?;
+
+    // Linear, so compositing them back together gives the render before post effects
+    for (pass, pass_film) in &passes {
+        save_hdr(&pass_film.downscaled(downscale), &format!("{}_{}", name, pass.name()))?;
+    }
 
-        let mut cwd = std::env::current_dir().expect("Unable to get cwd");
-        cwd.push("ray.png");
-        img.save(cwd).expect("Unable to save with default name");
+    if stats::ENABLED {
+        let mut sorted = heat.clone();
+        sorted.sort_unstable();
+        let max = sorted.last().copied().unwrap_or(0);
+        let total: u64 = sorted.iter().sum();
+        eprintln!("Traversal work: {} tests in total, {} at worst in a pixel", total, max);
+
+        // Normalized to the 99th percentile, so the heatmap is relative to this scene
+        // and a few pathological pixels don't wash everything else out
+        let scale = sorted[sorted.len() * 99 / 100].max(1) as f64;
+        let heatmap = RgbImage::from_fn(rendered_width as u32, rendered_height as u32, |x, y| {
+            let work = heat[y as usize * rendered_width + x as usize];
+            color::heat(work as f64 / scale).to_rgb(1.0)
+        });
+        save(&heatmap, &format!("{}_heatmap", name))?;
+    }
+
+    *cache = Some(SceneCache { key, scene, camera });
+    Ok(())
+}
+
+/// Render the scene `runs` times with consecutive seeds starting from --seed's, for
+/// evaluating samplers: their mean is returned, and the per pixel variance saved as
+/// <name>_variance.exr plus a false color <name>_variance.png. The seeds go to
+/// <name>_seeds.txt, so the study can be repeated or extended later.
+fn variance_study(scene: &Scene, flags: &[&str], (width, height): (usize, usize), session: &Session, runs: usize, name: &str) -> Result<Rendered, Error> {
+    if runs < 2 {
+        return Err(Error::Usage("--variance needs at least 2 renders".to_string()));
     }
+    let first: u64 = parse_flag(flags, "--seed", "a whole number")?.unwrap_or(SEED);
+    let seeds: Vec<u64> = (0..runs as u64).map(|run| first.wrapping_add(run)).collect();
+
+    let mut variance: Option<Variance> = None;
+    let (mut heat, mut panicked) = (Vec::new(), 0);
+    let mut samples = 0;
+    for (run, &seed) in seeds.iter().enumerate() {
+        eprintln!("Variance study: render {} of {}, seed {}", run + 1, runs, seed);
+        let renderer = configure_renderer(flags, width, height, session)?.with_seed(seed);
+        let rendered = renderer.render(scene);
+        if session.control.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        samples = renderer.samples();
+        let (film_width, film_height) = (rendered.film.width(), rendered.film.height());
+        variance.get_or_insert_with(|| Variance::new(film_width, film_height)).add(&rendered.film);
+        heat.resize(rendered.heat.len(), 0);
+        heat.iter_mut().zip(&rendered.heat).for_each(|(total, heat)| *total += heat);
+        panicked += rendered.panicked;
+    }
+    let variance = variance.expect("at least 2 renders");
+
+    let per_pixel = variance.variance();
+    let mut sorted: Vec<f64> = per_pixel.pixels().iter().map(|v| (v.x + v.y + v.z) / 3.0).collect();
+    sorted.sort_unstable_by(f64::total_cmp);
+    eprintln!(
+        "Variance over {} renders at {} spp: {:.3e} per pixel on average, {:.3e} at the 99th percentile",
+        variance.renders(), samples, sorted.iter().sum::<f64>() / sorted.len() as f64, sorted[sorted.len() * 99 / 100]
+    );
+    save_hdr(&per_pixel, &format!("{}_variance", name))?;
+    save(&variance.map(), &format!("{}_variance", name))?;
+
+    let path = format!("{}_seeds.txt", name);
+    let registry: String = seeds.iter().map(|seed| format!("{}\n", seed)).collect();
+    std::fs::write(&path, registry).map_err(|source| Error::Io { path, source })?;
+
+    Ok(Rendered { film: variance.mean().clone(), heat, panicked, passes: Vec::new() })
 }
 
-fn random_scene() -> HittableList {
-    // Init empty world
-    let mut world = HittableList::default();
+/// Render every job of a manifest one after the other (see batch.rs), with the
+/// command line's options applying to all of them. Jobs asking for the same scene
+/// as the one before share it, so list those together. A failed job doesn't stop
+/// the others, the run fails with the first failure once they're all done.
+fn batch(args: &[&str], flags: &[&str], session: &Session) -> Result<(), Error> {
+    let [path, ..] = args else {
+        return Err(Error::Usage("usage: batch <manifest>".to_string()));
+    };
+    let jobs = batch::parse(&read(path)?).map_err(|e| Error::Usage(format!("manifest '{}' {}", path, e)))?;
+
+    let mut cache = None;
+    let mut failed = Vec::new();
+    for (idx, job) in jobs.iter().enumerate() {
+        eprintln!("Job {} of {}: {} from line {}", idx + 1, jobs.len(), job.output, job.line);
+        let shared = flags.iter().map(|flag| flag.to_string()).collect();
+        let options = config::layered(&[join_values(job.options.clone()), shared]);
+        let options: Vec<&str> = options.iter().map(String::as_str).collect();
+        match render(&job.output, &job.scene, &options, session, &mut cache) {
+            Ok(()) => (),
+            Err(Error::Cancelled) => return Err(Error::Cancelled),
+            Err(e) => {
+                eprintln!("Job {} ({}) failed: {}", idx + 1, job.output, e);
+                failed.push(e);
+            },
+        }
+    }
+
+    match failed.is_empty() {
+        true => Ok(()),
+        false => {
+            eprintln!("{} of {} jobs failed", failed.len(), jobs.len());
+            Err(failed.remove(0))
+        },
+    }
+}
 
-    // Make our ground sphere
-    let albedo = Color::new(0.5, 0.5, 0.5);
-    let mat_ground = Arc::new(Lambertian::new(albedo));
-    let sphere_ground = Sphere::new(0.0, -1000.0, 0.0, 1000.0, mat_ground);
-    world.add(sphere_ground);
+/// The last scene rendered, reused by the next render if it's the same one
+struct SceneCache {
+    key: String,
+    scene: Scene,
+    /// As built, before the render's lens options changed it
+    camera: Camera
+}
 
-    let p = Point3::new(4.0, 0.2, 0.0);
+/// Options `load_scene` reads, renders that only differ in the others can share a scene
+const SCENE_OPTIONS: [&str; 8] = ["--add=", "--light=", "--looks=", "--asset-path=", "--env-map=", "--env-rotation=", "--env-strength=", "--background="];
 
-    let rng = fastrand::Rng::new();
-    for i in -11..11 {
-        for j in -11..11 {
-            let i = i as f64;
-            let j = j as f64;
+/// What tells scenes apart for the cache
+fn scene_key(name: &str, aspect_ratio: f64, flags: &[&str]) -> String {
+    let options: Vec<&str> = flags.iter()
+        .copied()
+        .filter(|flag| SCENE_OPTIONS.iter().any(|option| flag.starts_with(option)))
+        .collect();
+    format!("{} {} {}", name, aspect_ratio, options.join(" "))
+}
+
+/// Image size from --resolution=<preset|WxH>, with --width=N and --aspect=W:H
+/// overriding its width and aspect ratio (the height follows from them)
+fn resolution(flags: &[&str]) -> Result<Resolution, Error> {
+    let preset = match flag_value(flags, "--resolution") {
+        Some(value) => Resolution::parse(value).map_err(Error::Usage)?,
+        None => Resolution::DEFAULT,
+    };
+    let aspect = match flag_value(flags, "--aspect") {
+        Some(value) => config::parse_aspect(value).map_err(Error::Usage)?,
+        None => preset.aspect_ratio(),
+    };
+    let width = parse_flag(flags, "--width", "a whole number")?.unwrap_or(preset.width);
+
+    Resolution::from_width(width, aspect).map_err(Error::Usage)
+}
+
+/// Build the named scene, then add the objects from --add and --light and apply --looks
+fn load_scene(name: &str, aspect_ratio: f64, flags: &[&str]) -> Result<Scene, Error> {
+    // Seed this thread's rng so random scenes come out the same every time
+    fastrand::seed(SEED);
+    let mut scene = match name {
+        "random" => scenes::random_scene(aspect_ratio),
+        "cornell" => scenes::cornell_box(aspect_ratio),
+        "empty" => scenes::empty(aspect_ratio),
+        _ => return Err(Error::Scene(format!("unknown scene '{}', expected random, cornell or empty", name))),
+    };
+
+    // --add="sphere 0,1,0 r=1 mat=glass" adds objects, see inline.rs
+    for line in flags.iter().filter_map(|flag| flag.strip_prefix("--add=")) {
+        inline::add(&mut scene.world, line, &assets(flags)).map_err(|e| Error::Scene(format!("--add '{}': {}", line, e)))?;
+    }
+
+    // --light="0,3,0 20" adds a point light, see light.rs
+    for spec in flags.iter().filter_map(|flag| flag.strip_prefix("--light=")) {
+        let light = light::parse(spec).map_err(|e| Error::Scene(format!("--light '{}': {}", spec, e)))?;
+        scene.world.add_light(light);
+    }
+
+    // --looks=file swaps materials by object name, for look variations of a scene
+    if let Some(path) = flag_value(flags, "--looks") {
+        // Images the rules use are looked for next to them first
+        let (path, assets) = assets(flags).resolve_referencing(path).map_err(Error::Scene)?;
+        let path = &path.display().to_string();
+        let text = read(path)?;
+        let rules = looks::parse_rules(&text, &assets).map_err(|e| Error::Scene(format!("looks '{}' {}", path, e)))?;
+        let changed = looks::apply(&mut scene.world, &rules);
+        eprintln!("Looks from '{}' changed {} objects", path, changed);
+    }
+
+    // --env-map=<hdr or exr> lights the scene with a captured environment, turned by
+    // --env-rotation=<degrees> and scaled by --env-strength=S
+    if let Some(path) = flag_value(flags, "--env-map") {
+        let path = assets(flags).resolve(path).map_err(Error::Scene)?;
+        let environment = EnvironmentMap::open(&path).map_err(Error::Scene)?
+            .with_rotation(parse_flag(flags, "--env-rotation", "an angle in degrees")?.unwrap_or(0.0))
+            .with_strength(parse_flag(flags, "--env-strength", "a number")?.unwrap_or(1.0));
+        scene.world.set_background(Arc::new(environment));
+    } else if let Some(spec) = flag_value(flags, "--background") {
+        // --background="sky 20 90", "solid 0,0,0" or "gradient <bottom> <top>"
+        let background = background::parse(spec).map_err(|e| Error::Scene(format!("--background '{}': {}", spec, e)))?;
+        scene.world.set_background(background);
+    }
+
+    Ok(scene)
+}
+
+/// The look images are saved with: a built-in preset from --grade=<name> or a grade
+/// file from --grade-file=<file> (see grade.rs), whose LUT is looked for next to it first
+fn grade(flags: &[&str]) -> Result<Option<Grade>, Error> {
+    if let Some(path) = flag_value(flags, "--grade-file") {
+        let (path, assets) = assets(flags).resolve_referencing(path).map_err(Error::Usage)?;
+        let path = &path.display().to_string();
+        let text = read(path)?;
+        return Grade::parse(&text, &assets).map(Some).map_err(|e| Error::Usage(format!("grade '{}' {}", path, e)));
+    }
+
+    match flag_value(flags, "--grade") {
+        Some(name) => Grade::preset(name)
+            .map(Some)
+            .ok_or_else(|| Error::Usage(format!("unknown --grade '{}', expected {}", name, Grade::PRESETS.join(", ")))),
+        None => Ok(None),
+    }
+}
 
-            let rand_mat = rng.f64();
-            let center = Point3::new(i + 0.9 * rng.f64(), 0.2, j + 0.9 * rng.f64());
+/// Shrink a scene down to the objects a problem still shows up with, rendering it
+/// small and with few samples (unless --spp says otherwise) for every attempt.
+/// The predicate is "nan" (non finite pixels), "panic" or "slow=<seconds>".
+fn bisect_scene(args: &[&str], flags: &[&str], session: &Session) -> Result<(), Error> {
+    let [scene_name, predicate, ..] = args else {
+        return Err(Error::Usage("usage: bisect-scene <scene> <nan|panic|slow=seconds>".to_string()));
+    };
 
-            if (center - p).len() < 0.9 {
-                continue;
+    // Checked up front, the predicate runs deep inside the reduction
+    let slow_limit = predicate.strip_prefix("slow=").and_then(|secs| secs.parse::<f64>().ok());
+    if !matches!(*predicate, "nan" | "panic") && slow_limit.is_none() {
+        return Err(Error::Usage(format!("unknown predicate '{}', expected nan, panic or slow=<seconds>", predicate)));
+    }
+
+    // Tiny, but with the aspect ratio of the real render
+    let flags: Vec<&str> = flags.iter().copied().chain(["--spp=4"]).collect();
+    let small = Resolution::from_width(96, resolution(&flags)?.aspect_ratio()).map_err(Error::Usage)?;
+    let renderer = configure_renderer(&flags, small.width, small.height, session)?;
+    let fails = |mut scene: Scene| -> bool {
+        scene.world.build_bvh();
+
+        let start = Instant::now();
+        let rendered = renderer.render(&scene);
+        match (*predicate, slow_limit) {
+            ("nan", _) => rendered.film.pixels().iter().any(|pixel| !(pixel.x.is_finite() && pixel.y.is_finite() && pixel.z.is_finite())),
+            ("panic", _) => rendered.panicked > 0,
+            (_, limit) => start.elapsed().as_secs_f64() > limit.unwrap_or(f64::INFINITY),
+        }
+    };
+
+    let scene = load_scene(scene_name, small.aspect_ratio(), &flags)?;
+    let ids = bisect::reduce(&scene, fails)
+        .ok_or_else(|| Error::NotReproduced(format!("the whole scene isn't '{}', nothing to reduce", predicate)))?;
+
+    println!("Still '{}' with {} of {} objects:", predicate, ids.len(), scene.world.len());
+    for id in ids {
+        println!("  {}", scene.world.name(id).map_or_else(|| format!("{:?}", id), str::to_string));
+    }
+
+    Ok(())
+}
+
+/// Compare two images, printing their difference and optionally saving a FLIP map of it
+fn compare(args: &[&str], flags: &[&str]) -> Result<(), Error> {
+    let [a, b, ..] = args else {
+        return Err(Error::Usage("usage: compare <a.png> <b.png> [error map name]".to_string()));
+    };
+
+    let load = |path: &str| match image::open(path) {
+        Ok(img) => Ok(Film::from_image(&img.to_rgb8())),
+        Err(source) => Err(Error::Image { path: path.to_string(), source }),
+    };
+    let (a, b) = (load(a)?, load(b)?);
+    if a.width() != b.width() || a.height() != b.height() {
+        return Err(Error::Usage("images have different sizes, can't compare them".to_string()));
+    }
+
+    report(&a, &b);
+    if let Some(name) = args.get(2) {
+        save(&metrics::flip_map(&a, &b), &output_name(flags, name)?)?;
+    }
+
+    Ok(())
+}
+
+/// Print every metric comparing a to b
+fn report(a: &Film, b: &Film) {
+    println!("RMSE: {:.6}", metrics::rmse(a, b));
+    println!("SSIM: {:.6}", metrics::ssim(a, b));
+    println!("FLIP: {:.6}", metrics::flip(a, b));
+}
+
+/// Renderer set up from the options (--spp=N, --bounces=N, --min-throughput=T,
+/// --integrator=<path|preview>, --ao-distance=D, --seed=N, --no-stratify, --focus=<region>,
+/// --focus-reduce=N, --crop=<region>, --light-aovs, --lobe-aovs and --bloom) and
+/// hooked up to the session
+fn configure_renderer(flags: &[&str], width: usize, height: usize, session: &Session) -> Result<Renderer, Error> {
+    let number = |name: &str, default: u64| -> Result<u64, Error> {
+        Ok(parse_flag(flags, name, "a whole number")?.unwrap_or(default))
+    };
+
+    let samples = parse_count(flags, "--spp")?.unwrap_or(PIXEL_SAMPLES as usize);
+    let mut renderer = Renderer::new(width, height, samples)
+        .with_seed(number("--seed", SEED)?)
+        .with_control(session.control.clone());
+    // Left to the scene unless given
+    if let Some(bounces) = parse_flag(flags, "--bounces", "a whole number")? {
+        renderer = renderer.with_max_bounces(bounces);
+    }
+    if let Some(throughput) = parse_flag(flags, "--min-throughput", "a number")? {
+        renderer = renderer.with_min_throughput(throughput);
+    }
+    match flag_value(flags, "--integrator") {
+        None | Some("path") => (),
+        Some("preview") => {
+            let ao_distance = parse_flag(flags, "--ao-distance", "a distance")?.unwrap_or(f64::INFINITY);
+            renderer = renderer.with_integrator(Integrator::Preview { ao_distance });
+        },
+        Some(other) => return Err(Error::Usage(format!("--integrator takes path or preview, not '{}'", other))),
+    }
+    if switch(flags, "--no-stratify") {
+        renderer = renderer.with_stratification(false);
+    }
+    if let Some(focus) = flag_value(flags, "--focus") {
+        let region = parse_region(focus, width, height)
+            .ok_or_else(|| Error::Usage(format!("--focus takes x0..x1,y0..y1 as fractions of the image, not '{}'", focus)))?;
+        renderer = renderer.with_focus(region, number("--focus-reduce", 8)? as usize);
+    }
+    if let Some(crop) = flag_value(flags, "--crop") {
+        let region = parse_region(crop, width, height)
+            .ok_or_else(|| Error::Usage(format!("--crop takes x0..x1,y0..y1 as fractions of the image, not '{}'", crop)))?;
+        renderer = renderer.with_crop(region);
+    }
+    if switch(flags, "--light-aovs") {
+        renderer = renderer.with_passes(&Pass::LIGHT);
+    }
+    if switch(flags, "--lobe-aovs") {
+        renderer = renderer.with_passes(&Pass::LOBES);
+    }
+    // Before bloom and friends, so the preview shows the image as it's rendered
+    if let Some(monitor) = &session.monitor {
+        renderer = renderer.with_post_process(monitor.clone());
+    }
+    if switch(flags, "--bloom") {
+        renderer = renderer.with_post_process(Bloom::default());
+    }
+
+    Ok(renderer)
+}
+
+/// Region of a width x height film from "x0..x1,y0..y1", in fractions of the image
+/// (so it's the same with --downscale) and with y going down
+fn parse_region(region: &str, width: usize, height: usize) -> Option<Region> {
+    let range = |range: &str, size: usize| -> Option<std::ops::Range<usize>> {
+        let (start, end) = range.split_once("..")?;
+        let (start, end): (f64, f64) = (start.parse().ok()?, end.parse().ok()?);
+        let valid = (0.0..=1.0).contains(&start) && (0.0..=1.0).contains(&end) && start < end;
+        valid.then(|| (start * size as f64) as usize..(end * size as f64).ceil() as usize)
+    };
+
+    let (x, y) = region.split_once(',')?;
+    Some(Region { x: range(x, width)?, y: range(y, height)? })
+}
+
+/// What every render of this run shares
+struct Session {
+    /// Steered by commands from stdin and the monitor
+    control: Arc<RenderControl>,
+    /// --monitor's HTTP server, following every render
+    monitor: Option<Arc<dyn PostProcess>>
+}
+
+/// Listen for commands on stdin and start the monitor if asked to
+fn start_session(flags: &[&str]) -> Result<Session, Error> {
+    let control = Arc::new(RenderControl::default());
+
+    let listener = control.clone();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if !line.trim().is_empty() && !listener.command(&line) {
+                eprintln!("Unknown command '{}', expected pause, resume, threads <n> or cancel", line.trim());
             }
+        }
+    });
+
+    let monitor = match flag_value(flags, "--monitor") {
+        Some(address) => Some(start_monitor(address, control.clone())?),
+        None => None,
+    };
+
+    Ok(Session { control, monitor })
+}
+
+/// Serve the render's progress over HTTP, see monitor.rs
+#[cfg(feature = "monitor")]
+fn start_monitor(address: &str, control: Arc<RenderControl>) -> Result<Arc<dyn PostProcess>, Error> {
+    let monitor = monitor::Monitor::serve(address, control)
+        .map_err(|e| Error::Usage(format!("can't serve the monitor on '{}': {}", address, e)))?;
+    eprintln!("Monitoring the render on http://{}", address);
+    Ok(Arc::new(monitor))
+}
+
+#[cfg(not(feature = "monitor"))]
+fn start_monitor(_address: &str, _control: Arc<RenderControl>) -> Result<Arc<dyn PostProcess>, Error> {
+    Err(Error::Usage("--monitor needs the renderer built with the \"monitor\" feature".to_string()))
+}
+
+/// Options of the B side of an A/B comparison: the regular options, with every
+/// "--b-name[=value]" replacing "--name". Empty if there are no B options.
+fn b_side(flags: &[&str]) -> Vec<String> {
+    let overrides: Vec<String> = flags.iter()
+        .filter_map(|flag| flag.strip_prefix("--b-"))
+        .map(|flag| format!("--{}", flag))
+        .collect();
+    if overrides.is_empty() {
+        return overrides;
+    }
 
-            let material: Arc<dyn material::Material> = match rand_mat {
-                x if x < 0.8 => {   // Diffuse
-                    let albedo = Color::rand() * Color::rand();
-                    Arc::new(Lambertian::new(albedo))
-                },
-                x if x < 0.95 => {  // Metal
-                    let albedo = Color::rand_range(0.5..1.0);
-                    let fuzz = rng.f64() * 0.5; // f64 in range 0.0..0.5
-                    Arc::new(Metal::new(albedo, fuzz))
-                },
-                _ => Arc::new(Dielectric::new(1.5)) // Glass
-            };
-
-            let sphere = Sphere::new(center.x, center.y, center.z, 0.2, material);
-            world.add(sphere);
+    let key = |flag: &str| flag.split('=').next().unwrap_or(flag).to_string();
+    let overridden: Vec<String> = overrides.iter().map(|flag| key(flag)).collect();
+    flags.iter()
+        .filter(|flag| !flag.starts_with("--b-") && !overridden.contains(&key(flag)))
+        .map(|flag| flag.to_string())
+        .chain(overrides)
+        .collect()
+}
+
+/// Every option, from the command line, a bundle, RTW_* environment variables and the
+/// config file given with --config=<file> (or $RTW_CONFIG), in that order of priority
+fn layered_options(cli: &[&str], bundled: Vec<String>) -> Result<Vec<String>, Error> {
+    let env = config::from_env(std::env::vars());
+    let path = flag_value(cli, "--config").map(str::to_string).or_else(|| std::env::var(config::CONFIG_VAR).ok());
+    let file = match path {
+        Some(path) => config::from_file(&read(&path)?).map_err(|e| Error::Usage(format!("config '{}' {}", path, e)))?,
+        None => Vec::new(),
+    };
+
+    let cli = cli.iter().map(|flag| flag.to_string()).collect();
+    Ok(config::layered(&[cli, bundled, env, file]))
+}
+
+/// Options whose value usually comes as the next argument ("--add 'sphere ...'")
+/// are joined into "--add=sphere ..." like the rest
+fn join_values(args: Vec<String>) -> Vec<String> {
+    let mut joined = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.len()) {
+            ("--add", 1..) => joined.push(format!("--add={}", args.next().unwrap())),
+            ("--light", 1..) => joined.push(format!("--light={}", args.next().unwrap())),
+            _ => joined.push(arg),
         }
     }
 
-    // Instantiate the big glass sphere.
-    let material = Arc::new(Dielectric::new(1.5));
-    let sphere = Sphere::new(0.0, 1.0, 0.0, 1.0, material);
-    world.add(sphere);
+    joined
+}
+
+/// Value of a "--name=value" option
+fn flag_value<'a>(flags: &[&'a str], name: &str) -> Option<&'a str> {
+    flags.iter().find_map(|flag| flag.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Whether a "--name" switch is on. The first mention wins, so "--name=false"
+/// from a higher priority layer turns off one a lower layer turned on.
+fn switch(flags: &[&str], name: &str) -> bool {
+    let value = flags.iter().find_map(|flag| match flag.strip_prefix(name)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('='),
+    });
+    matches!(value, Some(value) if value != "false")
+}
+
+/// Parsed value of a "--name=value" option, `expected` describes it for the error
+fn parse_flag<T: std::str::FromStr>(flags: &[&str], name: &str, expected: &str) -> Result<Option<T>, Error> {
+    flag_value(flags, name)
+        .map(|value| value.parse().map_err(|_| Error::Usage(format!("{} takes {}, not '{}'", name, expected, value))))
+        .transpose()
+}
+
+/// Parsed value of a "--name=N" option counting something there has to be at least one of
+fn parse_count(flags: &[&str], name: &str) -> Result<Option<usize>, Error> {
+    match parse_flag(flags, name, "a whole number above 0")? {
+        Some(0) => Err(Error::Usage(format!("{} takes a whole number above 0, not 0", name))),
+        count => Ok(count),
+    }
+}
+
+/// Fail early (and cleanly) if the film and its buffers can't be allocated,
+/// instead of aborting somewhere in the middle of the render
+fn check_memory(width: usize, height: usize) -> Result<(), Error> {
+    // The film plus the downscaled copy, the traversal heat and some headroom
+    let pixels = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(3));
+    pixels
+        .and_then(|pixels| Vec::<Color>::new().try_reserve_exact(pixels).ok())
+        .ok_or(Error::OutOfMemory { width, height })
+}
 
-    // Instantiate the big opaque sphere.
-    let albedo = Color::new(0.4, 0.2, 0.1);
-    let material = Arc::new(Lambertian::new(albedo));
-    let sphere = Sphere::new(-4.0, 1.0, 0.0, 1.0, material);
-    world.add(sphere);
+fn read(path: &str) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|source| Error::Io { path: path.to_string(), source })
+}
 
-    // Instantiate the big metallic sphere.
-    let albedo = Color::new(0.7, 0.6, 0.5);
-    let fuzz = 0.0;
-    let material = Arc::new(Metal::new(albedo, fuzz));
-    let sphere = Sphere::new(4.0, 1.0, 0.0, 1.0, material);
-    world.add(sphere);
+/// Where scene files (--looks, ...) are looked for, from --asset-path=<dirs> (or
+/// $RTW_ASSET_PATH), separated like $PATH
+fn assets(flags: &[&str]) -> AssetPaths {
+    AssetPaths::new(flag_value(flags, "--asset-path").unwrap_or(""))
+}
+
+/// Images named `name` go to --output-dir=<dir> (or $RTW_OUTPUT_DIR), created if
+/// needed, and the working directory otherwise
+fn output_name(flags: &[&str], name: &str) -> Result<String, Error> {
+    let Some(dir) = flag_value(flags, "--output-dir") else {
+        return Ok(name.to_string());
+    };
+
+    std::fs::create_dir_all(dir).map_err(|source| Error::Io { path: dir.to_string(), source })?;
+    Ok(Path::new(dir).join(name).display().to_string())
+}
+
+fn save(img: &RgbImage, name: &str) -> Result<(), Error> {
+    let path = format!("{}.png", name);
+    eprintln!("Saving image to path {}", path);
+    img.save(&path).map_err(|source| Error::Image { path, source })
+}
+
+fn save_hdr(film: &Film, name: &str) -> Result<(), Error> {
+    let path = format!("{}.exr", name);
+    eprintln!("Saving image to path {}", path);
+    film.to_hdr_image().save(&path).map_err(|source| Error::Image { path, source })
+}
 
-    world
-}
\ No newline at end of file
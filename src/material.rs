@@ -1,26 +1,52 @@
-use crate::color::Color;
+use std::sync::Arc;
+
+use crate::color::{self, Color};
+use crate::microfacet::{self, Charlie, Ggx};
+use crate::onb::Onb;
+use crate::sampler;
 pub use crate::hit::HitRecord;
 pub use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::ray::Lobe;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Point3, Vec3};
 
 pub type Scatter = (Ray, Color);
 
 pub trait Material: Send + Sync {
     fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter>;
+
+    /// Light given off at the hit, nothing for anything but light sources
+    fn emitted(&self, _hit: &HitRecord) -> Color {
+        crate::color::BLACK
+    }
+
+    /// Light sent back along `ray` per unit of light arriving at the hit from
+    /// `to_light` (unit length), cosine included: the BSDF evaluated for that pair
+    /// of directions. Only point lights (see light.rs) need it, as rays never find
+    /// them by chance. Black for perfect reflections and refractions (mirrors,
+    /// glass) which can't be evaluated, and for fuzzy metal which has no BSDF to.
+    fn lit(&self, _hit: &HitRecord, _ray: &Ray, _to_light: &Vec3) -> Color {
+        crate::color::BLACK
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color
+    albedo: Arc<dyn Texture>
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    /// Albedo looked up from the texture at every hit
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+    fn scatter(&self, hit: &HitRecord, _ray: &Ray) -> Option<Scatter> {
         let mut scatter_direction = hit.normal + Vec3::rand_unit_vec();
 
         // Catch degenerate scatter directions (infinity, NaN, ...)
@@ -29,19 +55,97 @@ impl Material for Lambertian {
         }
 
         let scattered = Ray::new(hit.p, scatter_direction);
-        let attenuation = self.albedo;
+        let attenuation = self.albedo.value(hit.u, hit.v, &hit.p);
         
         Some((scattered, attenuation))
     }
+
+    fn lit(&self, hit: &HitRecord, _ray: &Ray, to_light: &Vec3) -> Color {
+        let cos = hit.normal.dot(to_light).max(0.0);
+        cos / std::f64::consts::PI * self.albedo.value(hit.u, hit.v, &hit.p)
+    }
+}
+
+/// Oren-Nayar rough diffuse: the surface is made of tiny V shaped lambertian
+/// facets, which makes it flatter looking and brighter towards the light (think
+/// clay, concrete or the moon) than a plain Lambertian.
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64
+}
+
+impl OrenNayar {
+    /// Roughness is the standard deviation of the facets' slope angle in degrees,
+    /// 0 gives back a Lambertian
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), roughness)
+    }
+
+    /// Albedo looked up from the texture at every hit
+    pub fn textured(albedo: Arc<dyn Texture>, roughness: f64) -> Self {
+        let sigma2 = roughness.to_radians().powi(2);
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        Self { albedo, a, b }
+    }
+}
+
+impl OrenNayar {
+    /// A + B max(0, cos(φi - φo)) sin(α) tan(β), α the largest polar angle and β
+    /// the smallest, for local directions around the normal
+    fn factor(&self, wi: &Vec3, wo: &Vec3) -> f64 {
+        let (sin_i, sin_o) = ((1.0 - wi.z * wi.z).max(0.0).sqrt(), (1.0 - wo.z * wo.z).max(0.0).sqrt());
+        let cos_phi = match sin_i > 1e-6 && sin_o > 1e-6 {
+            true => ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0),
+            false => 0.0,
+        };
+        let (sin_alpha, tan_beta) = match wi.z.abs() > wo.z.abs() {
+            true => (sin_o, sin_i / wi.z.abs()),
+            false => (sin_i, sin_o / wo.z.abs().max(1e-6)),
+        };
+
+        self.a + self.b * cos_phi * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        let wi = microfacet::sample_cosine();
+
+        // Cosine sampling cancels the cos / π out of the weight
+        let albedo = self.albedo.value(hit.u, hit.v, &hit.p);
+        let attenuation = albedo * self.factor(&wi, &wo);
+        Some((Ray::new(hit.p, frame.to_world(&wi)), attenuation))
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        let wi = frame.to_local(to_light);
+        if wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        wi.z / std::f64::consts::PI * self.factor(&wi, &wo) * self.albedo.value(hit.u, hit.v, &hit.p)
+    }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    /// Reflections tinted by the texture at every hit
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         let fuzz = fuzz.min(1.0);
         Self { albedo, fuzz }
     }
@@ -49,9 +153,15 @@ impl Metal {
 
 impl Material for Metal {
     fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        // Fuzz: a point in the unit ball, with its direction from a stratified sample
+        let (u1, u2) = sampler::glossy_2d();
+        let (z, phi) = (1.0 - 2.0 * u1, 2.0 * std::f64::consts::PI * u2);
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        let offset = fastrand::f64().cbrt() * Vec3::new(radius * phi.cos(), radius * phi.sin(), z);
+
         let reflected = ray.direction.unit_vec().reflect(&hit.normal);
-        let scattered = Ray::new(hit.p, reflected + self.fuzz * Vec3::rand_in_unit_sphere());
-        let attenuation = self.albedo;
+        let scattered = Ray::new(hit.p, reflected + self.fuzz * offset).with_lobe(Lobe::Specular);
+        let attenuation = self.albedo.value(hit.u, hit.v, &hit.p);
 
         match scattered.direction.dot(&hit.normal) > 0.0 {
             true => Some((scattered, attenuation)),
@@ -60,13 +170,733 @@ impl Material for Metal {
     }
 }
 
+/// Ideal mirror: reflects everything exactly about the normal without drawing a
+/// single random number, so renders of it are deterministic. A reference for
+/// debugging reflections and for regression renders.
+pub struct Mirror {
+    albedo: Color
+}
+
+impl Mirror {
+    /// Perfect mirror, reflects all the light
+    pub fn new() -> Self {
+        Self::tinted(color::WHITE)
+    }
+
+    /// Reflects `albedo` of the light, i.e: a colored or darker mirror
+    pub fn tinted(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Default for Mirror {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Material for Mirror {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let reflected = ray.direction.unit_vec().reflect(&hit.normal);
+        Some((Ray::new(hit.p, reflected).with_lobe(Lobe::Specular), self.albedo))
+    }
+}
+
+/// Light source: emits the same light all over (and in every direction) and
+/// doesn't reflect anything
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+    strength: f64
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(emit)))
+    }
+
+    /// Emission looked up from the texture at every hit, i.e. a screen showing an image
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self { emit, strength: 1.0 }
+    }
+
+    /// Multiply the emission, so lights can be far brighter than their color's 1.0
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _hit: &HitRecord, _ray: &Ray) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        self.strength * self.emit.value(hit.u, hit.v, &hit.p)
+    }
+}
+
+/// Phase function of a participating medium (smoke, fog, ...) that scatters
+/// light equally in every direction, no matter where it came from
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    /// Albedo looked up from the texture (by position, inside a volume)
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, hit: &HitRecord, _ray: &Ray) -> Option<Scatter> {
+        let scattered = Ray::new(hit.p, Vec3::rand_unit_vec());
+        Some((scattered, self.albedo.value(hit.u, hit.v, &hit.p)))
+    }
+
+    // Phase functions have no cosine
+    fn lit(&self, hit: &HitRecord, _ray: &Ray, _to_light: &Vec3) -> Color {
+        self.albedo.value(hit.u, hit.v, &hit.p) / (4.0 * std::f64::consts::PI)
+    }
+}
+
+/// Metallic-roughness PBR material, the model glTF and most modern tools use:
+/// a GGX specular lobe on top of a diffuse base. Metals have no diffuse part
+/// and tint their reflections with the base color, dielectrics reflect ~4%.
+pub struct Pbr {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    ggx: Ggx
+}
+
+impl Pbr {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(base_color)), metallic, roughness)
+    }
+
+    /// Base color looked up from the texture at every hit
+    pub fn textured(base_color: Arc<dyn Texture>, metallic: f64, roughness: f64) -> Self {
+        Self { base_color, metallic: metallic.clamp(0.0, 1.0), ggx: Ggx::new(roughness) }
+    }
+
+    /// The specular reflectance head on and the diffuse color at the hit
+    fn colors(&self, hit: &HitRecord) -> (Color, Color) {
+        // Dielectrics reflect 4% head on, metals their own color
+        let base_color = self.base_color.value(hit.u, hit.v, &hit.p);
+        let dielectric_f0 = Color::new(0.04, 0.04, 0.04);
+        let f0 = (1.0 - self.metallic) * dielectric_f0 + self.metallic * base_color;
+        (f0, (1.0 - self.metallic) * base_color)
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;    // Only possible with shading normals, seen from behind
+        }
+
+        let (f0, diffuse) = self.colors(hit);
+
+        // Pick one of the lobes, in proportion to (roughly) how much each one reflects.
+        // Each lobe's weight is divided by the chance of picking it, which keeps the
+        // sum of the two unbiased.
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let reflected = microfacet::schlick(f0, wo.z);
+        let specular_weight = average(reflected);
+        let total_weight = specular_weight + average(diffuse);
+        let specular_chance = match total_weight > 0.0 {
+            true => specular_weight / total_weight,
+            false => 1.0,
+        };
+
+        let (wi, attenuation, lobe) = match fastrand::f64() < specular_chance {
+            true => {
+                let h = self.ggx.sample_visible_normal(&wo, sampler::glossy_2d());
+                let wi = (-wo).reflect(&h);
+                let fresnel = microfacet::schlick(f0, wo.dot(&h));
+                (wi, fresnel * self.ggx.g2(&wo, &wi) / self.ggx.g1(&wo) / specular_chance, Lobe::Specular)
+            },
+            false => {
+                // Whatever isn't reflected by the specular layer reaches the diffuse base
+                let wi = microfacet::sample_cosine();
+                (wi, (color::WHITE - reflected) * diffuse / (1.0 - specular_chance), Lobe::Diffuse)
+            },
+        };
+
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(lobe), attenuation)),
+            false => None,
+        }
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        let (f0, diffuse) = self.colors(hit);
+        let h = (wo + wi).unit_vec();
+        let specular = self.ggx.reflected(&wo, &wi) * microfacet::schlick(f0, wo.dot(&h));
+        specular + wi.z / std::f64::consts::PI * (color::WHITE - microfacet::schlick(f0, wo.z)) * diffuse
+    }
+}
+
+/// Old school Blinn-Phong (diffuse color + specular color with a shininess
+/// exponent), handy to match OpenGL era assets and to compare against the physically
+/// based materials. Uses the normalized specular lobe, so the highlight keeps its
+/// brightness as it narrows, but that lobe isn't energy conserving (it can reflect
+/// more than comes in, more so at grazing angles): keep diffuse + specular at or
+/// below 1. Sampling is unbiased, it converges to exactly this model, excess and all.
+pub struct BlinnPhong {
+    diffuse: Arc<dyn Texture>,
+    specular: Color,
+    shininess: f64
+}
+
+impl BlinnPhong {
+    pub fn new(diffuse: Color, specular: Color, shininess: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(diffuse)), specular, shininess)
+    }
+
+    /// Diffuse color looked up from the texture at every hit
+    pub fn textured(diffuse: Arc<dyn Texture>, specular: Color, shininess: f64) -> Self {
+        Self { diffuse, specular, shininess: shininess.max(0.0) }
+    }
+}
+
+impl Material for BlinnPhong {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Pick a lobe in proportion to its color, dividing by the chance to stay unbiased
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let diffuse = self.diffuse.value(hit.u, hit.v, &hit.p);
+        let total = average(self.specular) + average(diffuse);
+        let specular_chance = match total > 0.0 {
+            true => average(self.specular) / total,
+            false => return None,
+        };
+
+        let (wi, attenuation, lobe) = match fastrand::f64() < specular_chance {
+            true => {
+                // Half vector with density ∝ cos^n, the lobe's own shape
+                let (u1, u2) = sampler::glossy_2d();
+                let cos = u1.powf(1.0 / (self.shininess + 1.0));
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * std::f64::consts::PI * u2;
+                let h = Vec3::new(sin * phi.cos(), sin * phi.sin(), cos);
+                let wi = (-wo).reflect(&h);
+
+                // f * cos / pdf with f = (n + 8) / 8π * cos^n and pdf = (n + 1) / 2π * cos^n / 4(wo.h)
+                let n = self.shininess;
+                let weight = (n + 8.0) / (n + 1.0) * wi.z.max(0.0) * wo.dot(&h);
+                (wi, weight * self.specular / specular_chance, Lobe::Specular)
+            },
+            false => (microfacet::sample_cosine(), diffuse / (1.0 - specular_chance), Lobe::Diffuse),
+        };
+
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(lobe), attenuation)),
+            false => None,
+        }
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        let n = self.shininess;
+        let h = (wo + wi).unit_vec();
+        let specular = (n + 8.0) / (8.0 * std::f64::consts::PI) * h.z.max(0.0).powf(n);
+        let diffuse = self.diffuse.value(hit.u, hit.v, &hit.p) / std::f64::consts::PI;
+        wi.z * (diffuse + specular * self.specular)
+    }
+}
+
+/// Cloth like velvet or microfiber: a diffuse base under a sheen that lights up
+/// at grazing angles, from fibers catching the light along the silhouette. The
+/// sheen is added on top of the base and reflects up to ~2/3 of the light right at
+/// grazing angles, keep base + 2/3 sheen at or below 1.
+pub struct Sheen {
+    base: Arc<dyn Texture>,
+    sheen: Color,
+    charlie: Charlie
+}
+
+impl Sheen {
+    /// Roughness in 0.0..=1.0, lower gives a thinner, sharper rim
+    pub fn new(base: Color, sheen: Color, roughness: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(base)), sheen, roughness)
+    }
+
+    /// Base color looked up from the texture at every hit, i.e. patterned fabric
+    pub fn textured(base: Arc<dyn Texture>, sheen: Color, roughness: f64) -> Self {
+        Self { base, sheen, charlie: Charlie::new(roughness) }
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Both lobes are wide, cosine sampling them together is good enough: f * cos / pdf = π * f
+        let wi = microfacet::sample_cosine();
+        if wi.z <= 0.0 {
+            return None;
+        }
+        let h = (wo + wi).unit_vec();
+        let sheen = std::f64::consts::PI * self.charlie.d(&h) * Charlie::visibility(&wo, &wi);
+
+        let base = self.base.value(hit.u, hit.v, &hit.p);
+        Some((Ray::new(hit.p, frame.to_world(&wi)), base + sheen * self.sheen))
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        let h = (wo + wi).unit_vec();
+        let sheen = self.charlie.d(&h) * Charlie::visibility(&wo, &wi);
+        wi.z * (self.base.value(hit.u, hit.v, &hit.p) / std::f64::consts::PI + sheen * self.sheen)
+    }
+}
+
+/// Brushed metal: GGX reflection that's rougher across the brushing direction
+/// than along it, stretching highlights into streaks. The brushing follows the
+/// surface's u direction, so it goes around spheres and along u on meshes.
+pub struct AnisotropicMetal {
+    albedo: Color,
+    ggx: Ggx
+}
+
+impl AnisotropicMetal {
+    /// Roughness along the u (tangent) direction and across it, both in 0.0..=1.0
+    pub fn new(albedo: Color, roughness_u: f64, roughness_v: f64) -> Self {
+        Self { albedo, ggx: Ggx::anisotropic(roughness_u, roughness_v) }
+    }
+
+    fn frame(hit: &HitRecord) -> Onb {
+        // Geometry without a tangent still gets some (arbitrary) consistent frame
+        match &hit.tangent {
+            Some(tangent) => Onb::from_wu(&hit.normal, tangent),
+            None => Onb::from_w(&hit.normal),
+        }
+    }
+}
+
+impl Material for AnisotropicMetal {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let h = self.ggx.sample_visible_normal(&wo, sampler::glossy_2d());
+        let wi = (-wo).reflect(&h);
+        let attenuation = microfacet::schlick(self.albedo, wo.dot(&h)) * self.ggx.g2(&wo, &wi) / self.ggx.g1(&wo);
+
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(Lobe::Specular), attenuation)),
+            false => None,
+        }
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Self::frame(hit);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        let h = (wo + wi).unit_vec();
+        self.ggx.reflected(&wo, &wi) * microfacet::schlick(self.albedo, wo.dot(&h))
+    }
+}
+
+/// Thin clear glossy coat over any other material (car paint, lacquered wood).
+/// The coat reflects what Fresnel says it does, only the rest of the light
+/// reaches the base material underneath.
+pub struct Clearcoat {
+    base: Arc<dyn Material>,
+    ggx: Ggx,
+    f0: f64
+}
+
+impl Clearcoat {
+    /// A coat with the given roughness (0.0..=1.0) and a lacquer like
+    /// refraction index of 1.5
+    pub fn new(base: Arc<dyn Material>, roughness: f64) -> Self {
+        Self { base, ggx: Ggx::new(roughness), f0: 0.04 }
+    }
+
+    /// Refraction index of the coat, higher reflects more
+    pub fn with_ior(mut self, ior: f64) -> Self {
+        self.f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+        self
+    }
+}
+
+impl Material for Clearcoat {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return self.base.scatter(hit, ray);
+        }
+
+        // The coat reflects with probability F, so the base gets exactly the
+        // light the coat let through and its weight needs no correction
+        let f0 = self.f0 * color::WHITE;
+        let coat_chance = microfacet::schlick(f0, wo.z).x;
+        if fastrand::f64() >= coat_chance {
+            return self.base.scatter(hit, ray);
+        }
+
+        let h = self.ggx.sample_visible_normal(&wo, sampler::glossy_2d());
+        let wi = (-wo).reflect(&h);
+        let fresnel = microfacet::schlick(f0, wo.dot(&h));
+        let attenuation = fresnel * self.ggx.g2(&wo, &wi) / self.ggx.g1(&wo) / coat_chance;
+
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(Lobe::Specular), attenuation)),
+            false => None,
+        }
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        self.base.emitted(hit)
+    }
+
+    // The coat's reflection, plus the base under whatever the coat let through
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 {
+            return self.base.lit(hit, ray, to_light);
+        }
+
+        let f0 = self.f0 * color::WHITE;
+        let h = (wo + wi).unit_vec();
+        let coat = self.ggx.reflected(&wo, &wi) * microfacet::schlick(f0, wo.dot(&h));
+        coat + (1.0 - microfacet::schlick(f0, wo.z).x) * self.base.lit(hit, ray, to_light)
+    }
+}
+
+/// Plastic: a diffuse substrate under a dielectric coat, both part of one surface.
+/// Unlike `Clearcoat` over a Lambertian, light reaching the substrate is refracted
+/// through the coat on the way in and out, and partly reflected back down by it,
+/// bouncing on the substrate again: colors come out deeper and more saturated,
+/// and the coat's Fresnel shows in both layers.
+pub struct Plastic {
+    albedo: Arc<dyn Texture>,
+    ggx: Ggx,
+    ior: f64
+}
+
+impl Plastic {
+    /// Substrate color and coat roughness (0.0..=1.0), the coat has a refraction
+    /// index of 1.5 (most plastics)
+    pub fn new(albedo: Color, roughness: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), roughness)
+    }
+
+    /// Substrate color looked up from the texture at every hit
+    pub fn textured(albedo: Arc<dyn Texture>, roughness: f64) -> Self {
+        Self { albedo, ggx: Ggx::new(roughness), ior: 1.5 }
+    }
+
+    pub fn with_ior(mut self, ior: f64) -> Self {
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    /// Share of diffuse light inside the coat it reflects back down. What makes it
+    /// out is what the (Schlick) coat lets in from outside, spread by η², so a white
+    /// substrate reflects exactly everything.
+    fn internal_reflectance(&self, f0: f64) -> f64 {
+        // Cosine weighted average of Schlick over the hemisphere, (1 - cos)^5 averages to 1/21
+        let external = f0 + (1.0 - f0) / 21.0;
+        1.0 - (1.0 - external) / (self.ior * self.ior)
+    }
+
+    /// What the substrate reflects of light crossing the coat at `cos` from the
+    /// normal, bounces under the coat included and the other crossing left out
+    fn substrate(&self, hit: &HitRecord, f0: f64, cos: f64) -> Color {
+        let leaving = 1.0 - microfacet::schlick(f0 * color::WHITE, cos).x;
+        let fdr = self.internal_reflectance(f0);
+        let albedo = self.albedo.value(hit.u, hit.v, &hit.p);
+        let bounced = Color::new(
+            albedo.x / (1.0 - albedo.x * fdr),
+            albedo.y / (1.0 - albedo.y * fdr),
+            albedo.z / (1.0 - albedo.z * fdr)
+        );
+        leaving / (self.ior * self.ior) * bounced
+    }
+}
+
+impl Material for Plastic {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // The coat reflects with probability F, the rest makes it to the substrate
+        let f0 = Dielectric::reflectance(1.0, self.ior) * color::WHITE;
+        let coat_chance = microfacet::schlick(f0, wo.z).x;
+        let (wi, attenuation, lobe) = match fastrand::f64() < coat_chance {
+            true => {
+                let h = self.ggx.sample_visible_normal(&wo, sampler::glossy_2d());
+                let wi = (-wo).reflect(&h);
+                let fresnel = microfacet::schlick(f0, wo.dot(&h));
+                (wi, fresnel * self.ggx.g2(&wo, &wi) / self.ggx.g1(&wo) / coat_chance, Lobe::Specular)
+            },
+            false => {
+                // Cosine sampled substrate, the (1 - F(wo)) of getting in cancels with
+                // the chance of picking it. Then (1 - F(wi)) getting back out, the
+                // radiance spread by 1/η² inside and the light the coat reflects
+                // back down bouncing around: ρ / (1 - ρ Fdr) in total.
+                let wi = microfacet::sample_cosine();
+                (wi, self.substrate(hit, f0.x, wi.z), Lobe::Diffuse)
+            },
+        };
+
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(lobe), attenuation)),
+            false => None,
+        }
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        // The substrate only gets what the coat lets in, and is seen through it the other way
+        let f0 = Dielectric::reflectance(1.0, self.ior) * color::WHITE;
+        let h = (wo + wi).unit_vec();
+        let coat = self.ggx.reflected(&wo, &wi) * microfacet::schlick(f0, wo.dot(&h));
+        let entering = 1.0 - microfacet::schlick(f0, wo.z).x;
+        coat + entering * wi.z / std::f64::consts::PI * self.substrate(hit, f0.x, wi.z)
+    }
+}
+
+/// Blend of two materials: every scatter picks b with the mask's chance (its
+/// brightness at the hit) and a otherwise, so the average is the mix of both.
+/// Rusty metal, dirt over paint, glossy specks on diffuse, ... without a new BSDF.
+pub struct Mix {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    mask: Arc<dyn Texture>
+}
+
+impl Mix {
+    /// The same amount of b (0.0..=1.0) everywhere
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, amount: f64) -> Self {
+        Self::masked(a, b, Arc::new(SolidColor::new(amount * color::WHITE)))
+    }
+
+    /// How much of b varies over the surface, looked up from the mask texture
+    pub fn masked(a: Arc<dyn Material>, b: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        Self { a, b, mask }
+    }
+
+    fn amount(&self, hit: &HitRecord) -> f64 {
+        let value = self.mask.value(hit.u, hit.v, &hit.p);
+        ((value.x + value.y + value.z) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        match fastrand::f64() < self.amount(hit) {
+            true => self.b.scatter(hit, ray),
+            false => self.a.scatter(hit, ray),
+        }
+    }
+
+    // Emission is blended outright, no need to pick
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        let amount = self.amount(hit);
+        (1.0 - amount) * self.a.emitted(hit) + amount * self.b.emitted(hit)
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let amount = self.amount(hit);
+        (1.0 - amount) * self.a.lit(hit, ray, to_light) + amount * self.b.lit(hit, ray, to_light)
+    }
+}
+
+/// Wraps a material, bending its shading normal with a tangent space normal map:
+/// flat geometry gets the fine bumps and grooves baked into the map. The map's
+/// colors are the usual 0..1 encoding of -1..1 normals, (0.5, 0.5, 1) is flat.
+pub struct NormalMapped {
+    base: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+    strength: f64
+}
+
+impl NormalMapped {
+    pub fn new(base: Arc<dyn Material>, map: Arc<dyn Texture>) -> Self {
+        Self { base, map, strength: 1.0 }
+    }
+
+    /// Scale the map's slopes, 0 is flat and above 1 exaggerates them
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    /// The hit with its normal bent by the map
+    fn perturbed(&self, hit: &HitRecord) -> HitRecord {
+        let frame = match hit.tangent {
+            Some(tangent) => Onb::from_wu(&hit.normal, &tangent),
+            None => Onb::from_w(&hit.normal),
+        };
+
+        let texel = 2.0 * self.map.value(hit.u, hit.v, &hit.p) - color::WHITE;
+        let local = Vec3::new(self.strength * texel.x, self.strength * texel.y, texel.z.max(1e-3));
+
+        let mut hit = hit.clone();
+        hit.normal = frame.to_world(&local).unit_vec();
+        hit
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        self.base.scatter(&self.perturbed(hit), ray)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        self.base.emitted(hit)
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        self.base.lit(&self.perturbed(hit), ray, to_light)
+    }
+}
+
+/// Wraps a material, denting its shading normal with a height field: any texture,
+/// its brightness read as height. The slope is estimated by looking the height up
+/// a tiny step away from the hit along the surface, so it's driven by where the
+/// hit is in space and solid textures (noise, ...) work anywhere.
+pub struct Bumped {
+    base: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    scale: f64
+}
+
+impl Bumped {
+    /// `scale` is how far (in scene units) a height of 1.0 raises the surface
+    pub fn new(base: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { base, height, scale }
+    }
+
+    /// The hit with its normal tilted down the height field's slope
+    fn perturbed(&self, hit: &HitRecord) -> HitRecord {
+        // Small compared to the bumps, big enough to stay clear of rounding
+        const STEP: f64 = 1e-4;
+
+        let frame = match hit.tangent {
+            Some(tangent) => Onb::from_wu(&hit.normal, &tangent),
+            None => Onb::from_w(&hit.normal),
+        };
+        let height = |p: Point3| {
+            let value = self.height.value(hit.u, hit.v, &p);
+            (value.x + value.y + value.z) / 3.0
+        };
+
+        let here = height(hit.p);
+        let slope_u = (height(hit.p + STEP * frame.u) - here) / STEP;
+        let slope_v = (height(hit.p + STEP * frame.v) - here) / STEP;
+
+        let mut hit = hit.clone();
+        hit.normal = (frame.w - self.scale * (slope_u * frame.u + slope_v * frame.v)).unit_vec();
+        hit
+    }
+}
+
+impl Material for Bumped {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        self.base.scatter(&self.perturbed(hit), ray)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        self.base.emitted(hit)
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        self.base.lit(&self.perturbed(hit), ray, to_light)
+    }
+}
+
 pub struct Dielectric {
-    refraction: f64
+    refraction: f64,
+    /// Refraction index of the red, green and blue channels, if they differ
+    dispersion: Option<[f64; 3]>,
+    /// Fraction of each channel absorbed per unit of distance travelled inside
+    absorption: Color,
+    tint: Color
 }
 
 impl Dielectric {
     pub fn new(refraction: f64) -> Self {
-        Self { refraction }
+        Self { refraction, dispersion: None, absorption: Color::default(), tint: crate::color::WHITE }
+    }
+
+    /// Bend each color channel by its own amount, splitting white light into
+    /// spectral fringes (prisms, diamond fire). `abbe` is the glass' Abbe number,
+    /// lower disperses more: ~60 for crown glass, ~30 for flint, ~55 for diamond.
+    /// The refraction index given to `new` is the one of yellow light.
+    pub fn with_dispersion(mut self, abbe: f64) -> Self {
+        // Cauchy's n(λ) = a + b/λ², fitted to the index at the d line (587.6nm) and
+        // the Abbe number, which is (n_d - 1) / (n_F - n_C) for the F and C lines
+        let inv_squared = |nm: f64| 1.0 / (nm * nm);
+        let b = (self.refraction - 1.0) / (abbe.max(1.0) * (inv_squared(486.1) - inv_squared(656.3)));
+        let a = self.refraction - b * inv_squared(587.6);
+        // Representative wavelengths of the red, green and blue channels
+        self.dispersion = Some([630.0, 532.0, 465.0].map(|nm| a + b * inv_squared(nm)));
+        self
+    }
+
+    /// Tint light refracting into the glass, the same however thick it is: a quick
+    /// colored glass look. Reflections keep their color, like on real colored glass.
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Tint light travelling inside (Beer-Lambert): after `distance` units it's
+    /// left with `tint` of what it had, so thick glass ends up darker and more
+    /// saturated than its thin edges. The distance is in scene units.
+    pub fn with_absorption(mut self, tint: Color, distance: f64) -> Self {
+        let density = |channel: f64| -channel.clamp(1e-6, 1.0).ln() / distance.max(1e-9);
+        self.absorption = Color::new(density(tint.x), density(tint.y), density(tint.z));
+        self
     }
 
     /// Schlick's approximation for reflectance
@@ -80,10 +910,32 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
-        let attenuation = crate::color::WHITE;
+        // Hitting the inside of the surface means the ray just travelled through the medium
+        let attenuation = match hit.front_face {
+            true => crate::color::WHITE,
+            false => {
+                let distance = hit.t * ray.direction.len();
+                let transmittance = |density: f64| (-density * distance).exp();
+                Color::new(transmittance(self.absorption.x), transmittance(self.absorption.y), transmittance(self.absorption.z))
+            },
+        };
+        // A dispersive medium splits rays carrying every channel into single channel
+        // ones: a random channel, weighted 3 times since it's picked a third of the time
+        let (refraction, channel, attenuation) = match (self.dispersion, ray.channel) {
+            (None, channel) => (self.refraction, channel, attenuation),
+            (Some(indices), Some(channel)) => (indices[channel], Some(channel), attenuation),
+            (Some(indices), None) => {
+                let channel = fastrand::usize(..3);
+                let only = [0, 1, 2].map(|c| match c == channel {
+                    true => 3.0,
+                    false => 0.0,
+                });
+                (indices[channel], Some(channel), attenuation * Color::new(only[0], only[1], only[2]))
+            },
+        };
         let refrac_ratio = match hit.front_face {
-            true => 1.0 / self.refraction,
-            false => self.refraction,
+            true => 1.0 / refraction,
+            false => refraction,
         };
 
         let unit_direction = ray.direction.unit_vec();
@@ -95,12 +947,14 @@ impl Material for Dielectric {
         let mut cannot_refract = refrac_ratio * sin_theta > 1.0;
         cannot_refract |= Dielectric::reflectance(cos_theta, refrac_ratio) > fastrand::f64();
         
-        let direction = match cannot_refract {
-            true => unit_direction.reflect(&hit.normal),
-            false => unit_direction.refract(&hit.normal, refrac_ratio),
+        let (direction, attenuation, lobe) = match (cannot_refract, hit.front_face) {
+            (true, _) => (unit_direction.reflect(&hit.normal), attenuation, Lobe::Specular),
+            (false, true) => (unit_direction.refract(&hit.normal, refrac_ratio), attenuation * self.tint, Lobe::Transmission),
+            (false, false) => (unit_direction.refract(&hit.normal, refrac_ratio), attenuation, Lobe::Transmission),
         };
         
-        let scattered = Ray::new(hit.p, direction);
+        let mut scattered = Ray::new(hit.p, direction).with_lobe(lobe);
+        scattered.channel = channel;
         Some((scattered, attenuation))
     }
 }
\ No newline at end of file
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::Hit;
+use crate::interval::Interval;
+use crate::material::*;
+use crate::vec3::{Point3, Vec3};
+
+/// Indexed triangle mesh with a material table, each face picks its material
+/// by index into it (all 0 for single material meshes).
+/// If per-vertex normals are present, they're interpolated across each face
+/// (smooth shading), otherwise every face is shaded with its own flat normal.
+pub struct Mesh {
+    vertices: Vec<Point3>,
+    normals: Vec<Vec3>,
+    faces: Vec<[usize; 3]>,
+    face_materials: Vec<usize>,
+    materials: Vec<Arc<dyn Material>>,
+    terminator_offset: bool
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Point3>, faces: Vec<[usize; 3]>, material: Arc<dyn Material>) -> Self {
+        let face_materials = vec![0; faces.len()];
+        Self {
+            vertices,
+            normals: Vec::new(),
+            faces,
+            face_materials,
+            materials: vec![material],
+            terminator_offset: true
+        }
+    }
+
+    /// Replace the mesh's materials with a table where face i uses materials[face_materials[i]]
+    pub fn with_face_materials(mut self, materials: Vec<Arc<dyn Material>>, face_materials: Vec<usize>) -> Self {
+        assert_eq!(face_materials.len(), self.faces.len(), "Need exactly one material index per face");
+        assert!(
+            face_materials.iter().all(|&idx| idx < materials.len()),
+            "Face material index out of the material table's bounds"
+        );
+
+        self.materials = materials;
+        self.face_materials = face_materials;
+        self
+    }
+
+    /// Use the given per-vertex normals (one for every vertex) for shading
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(normals.len(), self.vertices.len(), "Need exactly one normal per vertex");
+        self.normals = normals.iter().map(Vec3::unit_vec).collect();
+        self
+    }
+
+    /// Generate per-vertex normals by averaging the normals of the faces around
+    /// each vertex - weighted by face area, since the cross product isn't normalized.
+    pub fn with_smooth_normals(mut self) -> Self {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for &[a, b, c] in &self.faces {
+            let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            let face_normal = (v1 - v0).cross(&(v2 - v0));
+            for idx in [a, b, c] {
+                normals[idx] += face_normal;
+            }
+        }
+
+        self.normals = normals.iter().map(Vec3::unit_vec).collect();
+        self
+    }
+
+    /// Whether smooth shaded faces start bounced rays from where the smooth surface
+    /// their normals describe would be, instead of on the flat face (on by default).
+    /// Fixes the blocky shadow terminator of coarse meshes approximating curved
+    /// surfaces (Hanika 2021, "Hacking the Shadow Terminator").
+    pub fn with_terminator_offset(mut self, terminator_offset: bool) -> Self {
+        self.terminator_offset = terminator_offset;
+        self
+    }
+
+    /// Split the mesh into one hittable per face, all sharing the mesh's data
+    pub fn into_triangles(self) -> Vec<Arc<dyn Hit>> {
+        let mesh = Arc::new(self);
+        (0..mesh.faces.len())
+            .map(|face| Arc::new(Triangle { mesh: Arc::clone(&mesh), face }) as Arc<dyn Hit>)
+            .collect()
+    }
+
+    /// Build the hit record for face `idx` hit at t with barycentric coordinates (u, v)
+    fn face_hit(&self, idx: usize, t: f64, (u, v): (f64, f64), ray: &Ray) -> HitRecord {
+        let [a, b, c] = self.faces[idx];
+        let material = &self.materials[self.face_materials[idx]];
+        let (v0, v1, v2) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+        let outward_normal = (v1 - v0).cross(&(v2 - v0)).unit_vec();
+
+        // Surface coordinates are the barycentric coordinates themselves
+        let hit = HitRecord::new(t, ray.at(t), outward_normal, (u, v), ray, Arc::clone(material));
+        let mut hit = hit.with_tangent(v1 - v0);    // u is vertex 1's barycentric weight
+        if !self.normals.is_empty() {
+            let w = 1.0 - u - v;
+            let shading_normal = w * self.normals[a] + u * self.normals[b] + v * self.normals[c];
+            hit.set_shading_normal(shading_normal.unit_vec());
+
+            // Lift p onto the tangent planes at the vertices it's below of, blended
+            // by the barycentric weights. Flat areas stay exactly where they are.
+            if self.terminator_offset {
+                let lift = |vertex: Point3, normal: Vec3| (hit.p - vertex).dot(&normal).min(0.0) * normal;
+                hit.p = hit.p - w * lift(v0, self.normals[a]) - u * lift(v1, self.normals[b]) - v * lift(v2, self.normals[c]);
+            }
+        }
+
+        hit
+    }
+
+    /// Möller–Trumbore ray/triangle intersection, returns (t, u, v) where u and v
+    /// are the barycentric coordinates of vertices 1 and 2.
+    fn intersect_face(&self, face: &[usize; 3], ray: &Ray, interval: &Interval) -> Option<(f64, f64, f64)> {
+        let v0 = self.vertices[face[0]];
+        let edge1 = self.vertices[face[1]] - v0;
+        let edge2 = self.vertices[face[2]] - v0;
+
+        let pvec = ray.direction.cross(&edge2);
+        let det = edge1.dot(&pvec);
+        if det.abs() < 1e-12 {
+            return None;    // Ray is parallel to the triangle's plane
+        }
+
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin - v0;
+        let u = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let v = ray.direction.dot(&qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        match interval.surrounds(t) {
+            true => Some((t, u, v)),
+            false => None,
+        }
+    }
+}
+
+impl Hit for Mesh {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        // Same as HittableList::shoot_ray - shrink the interval to keep the closest face
+        let mut interval = *interval;
+        let mut closest = None;
+        for (idx, face) in self.faces.iter().enumerate() {
+            if let Some((t, u, v)) = self.intersect_face(face, ray, &interval) {
+                interval.max = t;
+                closest = Some((idx, t, u, v));
+            }
+        }
+
+        let (idx, t, u, v) = closest?;
+        Some(self.face_hit(idx, t, (u, v), ray))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        bounds_of(&self.vertices)
+    }
+}
+
+/// A single face of a mesh, so big meshes can be split up into one hittable per
+/// triangle and put in a BVH instead of testing every face on every ray.
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    face: usize
+}
+
+impl Hit for Triangle {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let (t, u, v) = self.mesh.intersect_face(&self.mesh.faces[self.face], ray, interval)?;
+        Some(self.mesh.face_hit(self.face, t, (u, v), ray))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.mesh.faces[self.face];
+        bounds_of(&[self.mesh.vertices[a], self.mesh.vertices[b], self.mesh.vertices[c]])
+    }
+}
+
+/// Box around a set of points, padded so axis-aligned flat geometry
+/// (i.e: a quad or a single triangle) doesn't end up with a zero-width box
+fn bounds_of(points: &[Point3]) -> Aabb {
+    let first = points.first().copied().unwrap_or_default();
+    let (min, max) = points.iter().fold((first, first), |(min, max), point| {
+        (min.min(point), max.max(point))
+    });
+
+    Aabb::new(min, max).padded(1e-4)
+}
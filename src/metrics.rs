@@ -0,0 +1,378 @@
+//! Image comparison metrics, to put a number on "did this change hurt quality?"
+
+use crate::color::{self, Color};
+use crate::film::Film;
+
+use image::RgbImage;
+use rayon::prelude::*;
+
+/// Viewing conditions FLIP assumes, in pixels per degree of visual angle: a 0.7m
+/// wide 4K monitor seen from 0.7m away
+const PIXELS_PER_DEGREE: f64 = 67.0;
+
+/// Root mean squared error between two films of the same size, over all channels
+pub fn rmse(a: &Film, b: &Film) -> f64 {
+    let errors = squared_errors(a, b);
+    (errors.iter().sum::<f64>() / errors.len().max(1) as f64).sqrt()
+}
+
+/// False color map of the per pixel error (black: identical, red: worst).
+/// Normalized to the 99th percentile, so a few outliers don't hide everything else.
+pub fn error_map(a: &Film, b: &Film) -> RgbImage {
+    let errors: Vec<f64> = squared_errors(a, b).into_iter().map(f64::sqrt).collect();
+    let mut sorted = errors.clone();
+    sorted.sort_unstable_by(f64::total_cmp);
+    let scale = sorted.get(sorted.len() * 99 / 100).copied().unwrap_or(0.0);
+    let scale = match scale > 0.0 {
+        true => scale,
+        false => 1.0,
+    };
+
+    RgbImage::from_fn(a.width() as u32, a.height() as u32, |x, y| {
+        let error = errors[y as usize * a.width() + x as usize];
+        color::heat(error / scale).to_rgb(1.0)
+    })
+}
+
+/// Mean structural similarity (Wang et al. 2004) of the luminance, from 1.0 for
+/// identical films down to 0.0 (or even below) for unrelated ones. Unlike RMSE
+/// it looks at local means, contrast and structure, which is closer to what
+/// people notice.
+pub fn ssim(a: &Film, b: &Film) -> f64 {
+    assert!(a.width() == b.width() && a.height() == b.height(), "Can only compare films of the same size");
+    let width = a.width();
+
+    // On display encoded values, where the constants were tuned
+    let luma = |film: &Film| -> Vec<f64> {
+        film.pixels().iter().map(|c| luminance(c).clamp(0.0, 1.0).sqrt()).collect()
+    };
+    let (x, y) = (luma(a), luma(b));
+    let product = |p: &[f64], q: &[f64]| -> Vec<f64> { p.iter().zip(q).map(|(p, q)| p * q).collect() };
+
+    let window = gaussian_kernel(1.5, 5);
+    let blur = |plane: &[f64]| convolve(plane, width, &window, &window);
+    let (mean_x, mean_y) = (blur(&x), blur(&y));
+    let (mean_xx, mean_yy, mean_xy) = (blur(&product(&x, &x)), blur(&product(&y, &y)), blur(&product(&x, &y)));
+
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let total: f64 = (0..x.len()).map(|idx| {
+        let (mx, my) = (mean_x[idx], mean_y[idx]);
+        let var_x = mean_xx[idx] - mx * mx;
+        let var_y = mean_yy[idx] - my * my;
+        let covariance = mean_xy[idx] - mx * my;
+
+        ((2.0 * mx * my + C1) * (2.0 * covariance + C2)) / ((mx * mx + my * my + C1) * (var_x + var_y + C2))
+    }).sum();
+
+    total / x.len().max(1) as f64
+}
+
+/// Mean FLIP error (Andersson et al. 2020), in 0.0..=1.0
+pub fn flip(a: &Film, b: &Film) -> f64 {
+    let errors = flip_errors(a, b);
+    errors.iter().sum::<f64>() / errors.len().max(1) as f64
+}
+
+/// False color map of the per pixel FLIP error, black where the films look the
+/// same up to red where the difference is as bad as it gets
+pub fn flip_map(a: &Film, b: &Film) -> RgbImage {
+    let errors = flip_errors(a, b);
+    RgbImage::from_fn(a.width() as u32, a.height() as u32, |x, y| {
+        color::heat(errors[y as usize * a.width() + x as usize]).to_rgb(1.0)
+    })
+}
+
+/// Per pixel LDR-FLIP: how noticeable the difference between the films is when
+/// flipping between them. Both are filtered by the contrast sensitivity of the eye
+/// before comparing colors (so fine noise too small to see counts less), and the
+/// color difference is amplified where edges or points differ.
+pub fn flip_errors(a: &Film, b: &Film) -> Vec<f64> {
+    assert!(a.width() == b.width() && a.height() == b.height(), "Can only compare films of the same size");
+    let width = a.width();
+    let clamped = |film: &Film| -> Vec<Color> {
+        film.pixels().iter().map(|c| c.max(&color::BLACK).min(&color::WHITE)).collect()
+    };
+    let (a, b) = (clamped(a), clamped(b));
+
+    // Color pipeline: spatially filter in an opponent space, then compare in CIELab
+    let filtered = |pixels: &[Color]| -> Vec<Color> {
+        let opponent: Vec<Color> = pixels.iter().map(|c| to_ycxcz(&to_xyz(c))).collect();
+        let channels: Vec<Vec<f64>> = CSF.iter().enumerate()
+            .map(|(k, lobes)| {
+                let channel: Vec<f64> = opponent.iter().map(|c| c[k]).collect();
+                contrast_filter(&channel, width, lobes)
+            })
+            .collect();
+
+        (0..pixels.len())
+            .map(|idx| {
+                let rgb = from_xyz(&from_ycxcz(&Color::new(channels[0][idx], channels[1][idx], channels[2][idx])));
+                hunt(&to_lab(&to_xyz(&rgb.max(&color::BLACK).min(&color::WHITE))))
+            })
+            .collect()
+    };
+    let (lab_a, lab_b) = (filtered(&a), filtered(&b));
+
+    let green = hunt(&to_lab(&to_xyz(&Color::new(0.0, 1.0, 0.0))));
+    let blue = hunt(&to_lab(&to_xyz(&Color::new(0.0, 0.0, 1.0))));
+    let max_color = hyab(&green, &blue).powf(0.7);
+
+    // Feature pipeline: edges and points of the luminance
+    let sigma = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sigma).ceil() as usize;
+    let gaussian = gaussian_kernel(sigma, radius);
+    let edge = derivative_kernel(sigma, radius, |x| -x);
+    let point = derivative_kernel(sigma, radius, |x| x * x / (sigma * sigma) - 1.0);
+    let features = |pixels: &[Color]| -> (Vec<f64>, Vec<f64>) {
+        let y: Vec<f64> = pixels.iter().map(|c| to_xyz(c).y).collect();
+        let magnitude = |kernel: &[f64]| -> Vec<f64> {
+            let dx = convolve(&y, width, kernel, &gaussian);
+            let dy = convolve(&y, width, &gaussian, kernel);
+            dx.iter().zip(&dy).map(|(dx, dy)| dx.hypot(*dy)).collect()
+        };
+        (magnitude(&edge), magnitude(&point))
+    };
+    let ((edges_a, points_a), (edges_b, points_b)) = (features(&a), features(&b));
+
+    (0..a.len())
+        .map(|idx| {
+            // Compress the color difference so that 40% of the range covers 95% of the error
+            let (pc, pt) = (0.4, 0.95);
+            let color_error = hyab(&lab_a[idx], &lab_b[idx]).powf(0.7);
+            let color_error = match color_error < pc * max_color {
+                true => pt / (pc * max_color) * color_error,
+                false => pt + (color_error - pc * max_color) / (max_color - pc * max_color) * (1.0 - pt),
+            };
+
+            let feature_error = (edges_a[idx] - edges_b[idx]).abs()
+                .max((points_a[idx] - points_b[idx]).abs());
+            let feature_error = (feature_error / 2.0f64.sqrt()).powf(0.5);
+
+            color_error.powf(1.0 - feature_error)
+        })
+        .collect()
+}
+
+/// Per pixel mean and variance over renders of the same scene with different
+/// seeds, to measure how noisy a sampler or integrator is rather than eyeball it.
+/// Updated one render at a time (Welford's method), so they needn't all be kept.
+pub struct Variance {
+    renders: usize,
+    mean: Film,
+    /// Sum of squared differences from the mean, per channel
+    squares: Film
+}
+
+impl Variance {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { renders: 0, mean: Film::new(width, height), squares: Film::new(width, height) }
+    }
+
+    /// Add a render of the same size as the others
+    pub fn add(&mut self, film: &Film) {
+        self.renders += 1;
+        let renders = self.renders as f64;
+        let pixels = self.mean.pixels_mut().iter_mut().zip(self.squares.pixels_mut()).zip(film.pixels());
+        for ((mean, squares), &value) in pixels {
+            let before = value - *mean;
+            *mean += before / renders;
+            *squares += before * (value - *mean);
+        }
+    }
+
+    pub fn renders(&self) -> usize {
+        self.renders
+    }
+
+    pub fn mean(&self) -> &Film {
+        &self.mean
+    }
+
+    /// Unbiased variance of every pixel's channels, zero with fewer than 2 renders
+    pub fn variance(&self) -> Film {
+        let mut variance = self.squares.clone();
+        let renders = self.renders.saturating_sub(1).max(1) as f64;
+        for pixel in variance.pixels_mut() {
+            *pixel /= renders;
+        }
+
+        variance
+    }
+
+    /// False color map of the variance (averaged over the channels), normalized to
+    /// the 99th percentile like `error_map`
+    pub fn map(&self) -> RgbImage {
+        let variance = self.variance();
+        let values: Vec<f64> = variance.pixels().iter().map(|v| (v.x + v.y + v.z) / 3.0).collect();
+        let mut sorted = values.clone();
+        sorted.sort_unstable_by(f64::total_cmp);
+        let scale = sorted.get(sorted.len() * 99 / 100).copied().unwrap_or(0.0);
+        let scale = match scale > 0.0 {
+            true => scale,
+            false => 1.0,
+        };
+
+        RgbImage::from_fn(variance.width() as u32, variance.height() as u32, |x, y| {
+            color::heat(values[y as usize * variance.width() + x as usize] / scale).to_rgb(1.0)
+        })
+    }
+}
+
+/// Contrast sensitivity of the eye per YCxCz channel, as a sum of two gaussians
+/// (a1, b1, a2, b2) over the visual angle in degrees
+const CSF: [[f64; 4]; 3] = [
+    [1.0, 0.0047, 0.0, 1e-5],
+    [1.0, 0.0053, 0.0, 1e-5],
+    [34.1, 0.04, 13.5, 0.025],
+];
+
+/// Filter a channel with its (normalized) contrast sensitivity function
+fn contrast_filter(channel: &[f64], width: usize, &[a1, b1, a2, b2]: &[f64; 4]) -> Vec<f64> {
+    // exp(-π² r² / b) with r in degrees is a gaussian of this sigma in pixels
+    let sigma = |b: f64| PIXELS_PER_DEGREE * (b / 2.0).sqrt() / std::f64::consts::PI;
+    let radius = (3.0 * sigma(0.04)).ceil() as usize;
+
+    let mut filtered = vec![0.0; channel.len()];
+    let mut total_weight = 0.0;
+    for (a, b) in [(a1, b1), (a2, b2)] {
+        if a == 0.0 {
+            continue;
+        }
+
+        // Each lobe's share is its amplitude times the area of its kernel
+        let unnormalized: f64 = (-(radius as isize)..=radius as isize)
+            .map(|x| (-((x * x) as f64) / (2.0 * sigma(b) * sigma(b))).exp())
+            .sum();
+        let weight = a * (std::f64::consts::PI / b).sqrt() * unnormalized * unnormalized;
+
+        let kernel = gaussian_kernel(sigma(b), radius);
+        let blurred = convolve(channel, width, &kernel, &kernel);
+        filtered.iter_mut().zip(&blurred).for_each(|(sum, value)| *sum += weight * value);
+        total_weight += weight;
+    }
+
+    filtered.iter().map(|value| value / total_weight).collect()
+}
+
+/// Normalized gaussian of the given sigma, 2 * radius + 1 taps long
+fn gaussian_kernel(sigma: f64, radius: usize) -> Vec<f64> {
+    let kernel: Vec<f64> = (-(radius as isize)..=radius as isize)
+        .map(|x| (-((x * x) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|w| w / sum).collect()
+}
+
+/// Gaussian times `shape(x)`, with its positive and negative weights normalized
+/// to sum to 1 and -1, so feature strengths come out in 0.0..=1.0
+fn derivative_kernel(sigma: f64, radius: usize, shape: impl Fn(f64) -> f64) -> Vec<f64> {
+    let kernel: Vec<f64> = (-(radius as isize)..=radius as isize)
+        .map(|x| x as f64)
+        .map(|x| shape(x) * (-(x * x) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let positive: f64 = kernel.iter().filter(|w| **w > 0.0).sum();
+    let negative: f64 = -kernel.iter().filter(|w| **w < 0.0).sum::<f64>();
+
+    kernel.iter()
+        .map(|&w| match w > 0.0 {
+            true => w / positive,
+            false => w / negative,
+        })
+        .collect()
+}
+
+/// Separable convolution of a plane of values, clamping reads at the borders
+fn convolve(plane: &[f64], width: usize, kernel_x: &[f64], kernel_y: &[f64]) -> Vec<f64> {
+    let height = plane.len() / width;
+    let (radius_x, radius_y) = (kernel_x.len() / 2, kernel_y.len() / 2);
+
+    let mut horizontal = vec![0.0; plane.len()];
+    horizontal.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = kernel_x.iter().enumerate().map(|(k, w)| {
+                let sx = (x + k).saturating_sub(radius_x).min(width - 1);
+                w * plane[y * width + sx]
+            }).sum();
+        }
+    });
+
+    let mut convolved = vec![0.0; plane.len()];
+    convolved.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = kernel_y.iter().enumerate().map(|(k, w)| {
+                let sy = (y + k).saturating_sub(radius_y).min(height - 1);
+                w * horizontal[sy * width + x]
+            }).sum();
+        }
+    });
+
+    convolved
+}
+
+/// D65 reference white, in XYZ
+const WHITE_XYZ: [f64; 3] = [0.950489, 1.0, 1.088840];
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Linear sRGB to CIE XYZ
+fn to_xyz(c: &Color) -> Color {
+    Color::new(
+        0.4124564 * c.x + 0.3575761 * c.y + 0.1804375 * c.z,
+        0.2126729 * c.x + 0.7151522 * c.y + 0.0721750 * c.z,
+        0.0193339 * c.x + 0.1191920 * c.y + 0.9503041 * c.z,
+    )
+}
+
+/// CIE XYZ to linear sRGB
+fn from_xyz(c: &Color) -> Color {
+    Color::new(
+        3.2404542 * c.x - 1.5371385 * c.y - 0.4985314 * c.z,
+        -0.9692660 * c.x + 1.8760108 * c.y + 0.0415560 * c.z,
+        0.0556434 * c.x - 0.2040259 * c.y + 1.0572252 * c.z,
+    )
+}
+
+/// XYZ to the YyCxCz opponent space (a linearized CIELab)
+fn to_ycxcz(c: &Color) -> Color {
+    let [x, y, z] = [c.x / WHITE_XYZ[0], c.y / WHITE_XYZ[1], c.z / WHITE_XYZ[2]];
+    Color::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+fn from_ycxcz(c: &Color) -> Color {
+    let y = (c.x + 16.0) / 116.0;
+    Color::new((c.y / 500.0 + y) * WHITE_XYZ[0], y * WHITE_XYZ[1], (y - c.z / 200.0) * WHITE_XYZ[2])
+}
+
+/// XYZ to CIELab
+fn to_lab(c: &Color) -> Color {
+    const DELTA: f64 = 6.0 / 29.0;
+    let f = |t: f64| match t > DELTA * DELTA * DELTA {
+        true => t.cbrt(),
+        false => t / (3.0 * DELTA * DELTA) + 4.0 / 29.0,
+    };
+    let [x, y, z] = [f(c.x / WHITE_XYZ[0]), f(c.y / WHITE_XYZ[1]), f(c.z / WHITE_XYZ[2])];
+
+    Color::new(116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z))
+}
+
+/// Hunt effect: colors look less saturated the darker they are
+fn hunt(lab: &Color) -> Color {
+    Color::new(lab.x, 0.01 * lab.x * lab.y, 0.01 * lab.x * lab.z)
+}
+
+/// HyAB distance between two Lab colors, better than the euclidean one for large differences
+fn hyab(a: &Color, b: &Color) -> f64 {
+    let delta = *a - *b;
+    delta.x.abs() + (delta.y * delta.y + delta.z * delta.z).sqrt()
+}
+
+/// Per pixel squared error, averaged over the channels
+fn squared_errors(a: &Film, b: &Film) -> Vec<f64> {
+    assert!(a.width() == b.width() && a.height() == b.height(), "Can only compare films of the same size");
+    a.pixels().iter().zip(b.pixels())
+        .map(|(a, b)| (*a - *b).len_squared() / 3.0)
+        .collect()
+}
@@ -0,0 +1,178 @@
+//! Microfacet model building blocks. Everything works in the local shading
+//! frame (see `Onb`), where the surface normal is the z axis.
+
+use std::f64::consts::PI;
+
+use crate::color::{self, Color};
+use crate::vec3::Vec3;
+
+/// Trowbridge-Reitz (GGX) distribution of microfacet normals, possibly
+/// anisotropic: rougher along the local x axis than along y or vice versa
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64
+}
+
+impl Ggx {
+    /// Perceptual roughness in 0.0..=1.0, squared into the distribution's alpha.
+    /// Clamped away from 0, a perfect mirror is a delta the sampling can't handle.
+    pub fn new(roughness: f64) -> Self {
+        Self::anisotropic(roughness, roughness)
+    }
+
+    /// Different roughness along the local x (tangent) and y (bitangent) axes
+    pub fn anisotropic(roughness_x: f64, roughness_y: f64) -> Self {
+        let alpha = |roughness: f64| roughness.clamp(0.0, 1.0).powi(2).max(1e-3);
+        Self { alpha_x: alpha(roughness_x), alpha_y: alpha(roughness_y) }
+    }
+
+    /// Density of microfacets facing h
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let (x, y) = (h.x / self.alpha_x, h.y / self.alpha_y);
+        let denom = x * x + y * y + h.z * h.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * denom * denom)
+    }
+
+    /// Smith's Λ, how much of the microsurface is hidden seen from w
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        // alpha² * tan² θ, with the alpha projected on w's azimuth
+        let (x, y) = (self.alpha_x * w.x, self.alpha_y * w.y);
+        (-1.0 + (1.0 + (x * x + y * y) / cos2).sqrt()) / 2.0
+    }
+
+    /// Fraction of the microsurface visible from w
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction visible from both wo and wi (height correlated masking-shadowing)
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Reflection off the microfacets from wi to wo times cos(wi), Fresnel left
+    /// out: D(h) * G2 / (4 cos(wo) cos(wi)) * cos(wi), h the half vector
+    pub fn reflected(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (*wo + *wi).unit_vec();
+        self.d(&h) * self.g2(wo, wi) / (4.0 * wo.z)
+    }
+
+    /// Sample a microfacet normal among the ones visible from wo (Heitz 2018).
+    /// Reflecting wo about it gives a direction whose weight `f * cos / pdf`
+    /// is just `F * G2 / G1(wo)`, with very little variance. (u1, u2) is a uniform
+    /// sample in [0, 1)².
+    pub fn sample_visible_normal(&self, wo: &Vec3, (u1, u2): (f64, f64)) -> Vec3 {
+        // Stretch the view so the distribution becomes the hemisphere of normals
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vec();
+
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = match len2 > 0.0 {
+            true => Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt(),
+            false => Vec3::new(1.0, 0.0, 0.0),
+        };
+        let t2 = vh.cross(&t1);
+
+        // Sample the projected area of the visible half of the hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // And unstretch it back
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).unit_vec()
+    }
+}
+
+/// Berry's distribution (GTR with γ = 1), the long tailed lobe Disney's
+/// principled model uses for the clearcoat layer
+#[derive(Clone, Copy)]
+pub struct Gtr1 {
+    alpha: f64
+}
+
+impl Gtr1 {
+    pub fn new(alpha: f64) -> Self {
+        Self { alpha: alpha.clamp(1e-3, 0.999) }
+    }
+
+    /// Density of microfacets facing h
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * h.z * h.z))
+    }
+
+    /// Sample a microfacet normal proportionally to d(h) * cos(h), from a uniform
+    /// sample (u1, u2) in [0, 1)²
+    pub fn sample_normal(&self, (u1, u2): (f64, f64)) -> Vec3 {
+        let a2 = self.alpha * self.alpha;
+        let cos = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        Vec3::new(sin * phi.cos(), sin * phi.sin(), cos)
+    }
+}
+
+/// Estevez and Kulla's "Charlie" sheen distribution: microfibers standing up
+/// from the surface, so it's densest at grazing angles instead of around the normal
+#[derive(Clone, Copy)]
+pub struct Charlie {
+    alpha: f64
+}
+
+impl Charlie {
+    pub fn new(roughness: f64) -> Self {
+        // Below ~0.07 the lobe gets too thin for 1/alpha to behave
+        Self { alpha: roughness.clamp(0.07, 1.0) }
+    }
+
+    /// Density of microfacets facing h
+    pub fn d(&self, h: &Vec3) -> f64 {
+        let inv_alpha = 1.0 / self.alpha;
+        let sin = (1.0 - h.z * h.z).max(0.0).sqrt();
+        (2.0 + inv_alpha) * sin.powf(inv_alpha) / (2.0 * PI)
+    }
+
+    /// Neubelt and Pettineo's visibility term, a cheap stand in for the proper
+    /// shadowing of fibers (already divided by 4 cos(wo) cos(wi))
+    pub fn visibility(wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z))
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, f0 being the
+/// reflectance at normal incidence
+pub fn schlick(f0: Color, cos: f64) -> Color {
+    f0 + schlick_weight(cos) * (color::WHITE - f0)
+}
+
+/// Schlick's Fresnel weight (1 - cos)^5, what's left of Schlick without the f0
+pub fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Cosine weighted direction on the local hemisphere
+pub fn sample_cosine() -> Vec3 {
+    let p = Vec3::rand_in_unit_disk();
+    Vec3::new(p.x, p.y, (1.0 - p.x * p.x - p.y * p.y).max(0.0).sqrt())
+}
@@ -0,0 +1,152 @@
+//! Watch a long headless render from a browser: a tiny HTTP server (tiny_http)
+//! serving the render's progress, a low resolution preview and its stats as
+//! JSON. Only built with the "monitor" feature.
+//!
+//! - `/` a page showing all of it, refreshing itself
+//! - `/preview.jpg` the film so far, at most `PREVIEW_WIDTH` pixels wide
+//! - `/stats.json` progress, timings and resolution
+//! - `/pause`, `/resume`, `/cancel` and `/threads/<n>` steer the render (see
+//!   `RenderControl`) and answer with the stats
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::control::RenderControl;
+use crate::film::{Film, PostProcess, Region};
+
+use image::codecs::jpeg::JpegEncoder;
+use tiny_http::{Header, Response, Server};
+
+const PREVIEW_WIDTH: usize = 320;
+/// Encoding the preview isn't free, so it's only refreshed this often
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Render post process hook publishing the film's state to the HTTP server.
+/// The preview shows the film as the hooks added before it left it.
+pub struct Monitor {
+    state: Arc<Mutex<State>>
+}
+
+#[derive(Default)]
+struct State {
+    width: usize,
+    height: usize,
+    rows_done: usize,
+    passes_done: usize,
+    started: Option<Instant>,
+    /// How long the last pass took, once it's done
+    took: Option<Duration>,
+    preview: Vec<u8>,
+    preview_at: Option<Instant>
+}
+
+impl Monitor {
+    /// Start serving on `address` (i.e: "0.0.0.0:8080") from a background thread,
+    /// steering renders through `control`
+    pub fn serve(address: &str, control: Arc<RenderControl>) -> Result<Self, String> {
+        let server = Server::http(address).map_err(|e| e.to_string())?;
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let state = shared.lock().unwrap();
+                let path = request.url().split('?').next().unwrap_or("/");
+                let command = path.trim_start_matches('/').replace('/', " ");
+                let (body, content_type) = match path {
+                    "/preview.jpg" => (state.preview.clone(), "image/jpeg"),
+                    "/stats.json" => (state.json().into_bytes(), "application/json"),
+                    _ if control.command(&command) => (state.json().into_bytes(), "application/json"),
+                    _ => (PAGE.as_bytes().to_vec(), "text/html"),
+                };
+                drop(state);
+
+                let header = Header::from_bytes("Content-Type", content_type).unwrap();
+                // The client going away mid response isn't our problem
+                let _ = request.respond(Response::from_data(body).with_header(header));
+            }
+        });
+
+        Ok(Self { state })
+    }
+}
+
+impl State {
+    fn json(&self) -> String {
+        let progress = match self.height > 0 {
+            true => self.rows_done as f64 / self.height as f64,
+            false => 0.0,
+        };
+        let elapsed = match (self.took, self.started) {
+            (Some(took), _) => took.as_secs_f64(),
+            (None, Some(started)) => started.elapsed().as_secs_f64(),
+            (None, None) => 0.0,
+        };
+        let eta = match progress > 0.0 && progress < 1.0 {
+            true => format!("{:.1}", elapsed / progress - elapsed),
+            false => "null".to_string(),
+        };
+
+        format!(
+            "{{\"width\": {}, \"height\": {}, \"rows_done\": {}, \"passes_done\": {}, \"progress\": {:.4}, \"elapsed_secs\": {:.1}, \"eta_secs\": {}}}",
+            self.width, self.height, self.rows_done, self.passes_done, progress, elapsed, eta
+        )
+    }
+
+    fn refresh_preview(&mut self, film: &Film) {
+        let factor = film.width().div_ceil(PREVIEW_WIDTH);
+        let img = film.downscaled(factor).to_image();
+
+        let mut jpeg = Cursor::new(Vec::new());
+        if JpegEncoder::new_with_quality(&mut jpeg, 80).encode_image(&img).is_ok() {
+            self.preview = jpeg.into_inner();
+        }
+        self.preview_at = Some(Instant::now());
+    }
+}
+
+impl PostProcess for Monitor {
+    fn tile_done(&self, film: &mut Film, region: &Region) {
+        let mut state = self.state.lock().unwrap();
+        // First tile of a new pass (or render)
+        if state.started.is_none() || state.took.is_some() {
+            (state.started, state.took) = (Some(Instant::now()), None);
+            (state.width, state.height, state.rows_done) = (film.width(), film.height(), 0);
+        }
+        state.rows_done = (state.rows_done + region.y.len()).min(state.height);
+
+        if state.preview_at.is_none_or(|at| at.elapsed() >= PREVIEW_INTERVAL) {
+            state.refresh_preview(film);
+        }
+    }
+
+    fn pass_done(&self, film: &mut Film) {
+        let mut state = self.state.lock().unwrap();
+        state.passes_done += 1;
+        state.took = state.started.map(|started| started.elapsed());
+        state.refresh_preview(film);
+    }
+}
+
+const PAGE: &str = r#"<!doctype html>
+<html>
+<head><title>Render monitor</title></head>
+<body style="background: #222; color: #eee; font-family: monospace">
+<img id="preview" src="/preview.jpg" style="image-rendering: pixelated; width: 640px"><br>
+<button onclick="fetch('/pause')">Pause</button>
+<button onclick="fetch('/resume')">Resume</button>
+<button onclick="fetch('/threads/1')">1 thread</button>
+<button onclick="fetch('/threads/0')">All threads</button>
+<button onclick="fetch('/cancel')">Cancel</button>
+<pre id="stats"></pre>
+<script>
+setInterval(async () => {
+    document.getElementById("preview").src = "/preview.jpg?" + Date.now();
+    const stats = await (await fetch("/stats.json")).json();
+    document.getElementById("stats").textContent = JSON.stringify(stats, null, 2);
+}, 1000);
+</script>
+</body>
+</html>
+"#;
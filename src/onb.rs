@@ -0,0 +1,50 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis around a direction w, to go between world space and a
+/// local frame where w is the z axis (i.e: the shading frame around a normal)
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl Onb {
+    /// Basis around w (doesn't need to be normalized). Uses the branchless
+    /// construction from Duff et al. 2017, so it's continuous everywhere but
+    /// at w = -z where it just flips.
+    pub fn from_w(w: &Vec3) -> Self {
+        let w = w.unit_vec();
+        let sign = 1.0f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        let u = Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = Vec3::new(b, sign + w.y * w.y * a, -w.y);
+        Self { u, v, w }
+    }
+
+    /// Basis around w with u along the given direction, made perpendicular to w
+    /// (i.e: a surface's tangent, to line up anisotropic reflection with it).
+    /// Falls back to `from_w` if u is parallel to w.
+    pub fn from_wu(w: &Vec3, u: &Vec3) -> Self {
+        let w = w.unit_vec();
+        let u = *u - u.dot(&w) * w;
+        if u.len_squared() < 1e-16 {
+            return Self::from_w(&w);
+        }
+
+        let u = u.unit_vec();
+        Self { u, v: w.cross(&u), w }
+    }
+
+    /// Local coordinates to world space
+    pub fn to_world(&self, local: &Vec3) -> Vec3 {
+        local.x * self.u + local.y * self.v + local.z * self.w
+    }
+
+    /// World space direction to local coordinates
+    pub fn to_local(&self, world: &Vec3) -> Vec3 {
+        Vec3::new(world.dot(&self.u), world.dot(&self.v), world.dot(&self.w))
+    }
+}
@@ -0,0 +1,135 @@
+use crate::interval::Interval;
+use crate::scenes::Scene;
+
+use image::{Rgb, RgbImage};
+
+/// Glyphs are 3x5 pixels, one row per byte with the leftmost pixel in bit 2
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// Burn a line of text into a dark strip along the bottom of the image, like the
+/// slates on production dailies. Text is uppercased, characters the tiny font
+/// doesn't have are drawn as '?'. Scales with the image so it stays readable.
+pub fn burn_in(img: &mut RgbImage, text: &str) {
+    let scale = (img.height() / 240).max(1);
+    let margin = scale * 2;
+    let strip = GLYPH_HEIGHT * scale + 2 * margin;
+    if img.height() < strip {
+        return;
+    }
+
+    // Darken the strip instead of blanking it, the image still shows through
+    let top = img.height() - strip;
+    for y in top..img.height() {
+        for x in 0..img.width() {
+            let Rgb([r, g, b]) = *img.get_pixel(x, y);
+            img.put_pixel(x, y, Rgb([r / 4, g / 4, b / 4]));
+        }
+    }
+
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    for (idx, c) in text.chars().enumerate() {
+        let left = margin + idx as u32 * advance;
+        if left + GLYPH_WIDTH * scale > img.width() {
+            break;
+        }
+
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+
+                // Every font pixel is a scale x scale block
+                let (x, y) = (left + col * scale, top + margin + row as u32 * scale);
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        img.put_pixel(x + dx, y + dy, Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Tint what the camera sees sharp, to tune aperture and focus distance at a glance:
+/// green where the blur is under a pixel (the depth of field), brighter right on
+/// the focal plane. Shoots one ray per pixel, so it's cheap next to a render.
+pub fn depth_of_field(img: &mut RgbImage, scene: &Scene) {
+    let (width, height) = (img.width(), img.height());
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f64 + 0.5) / (width as f64 - 1.0);
+            let v = ((height - 1 - y) as f64 + 0.5) / (height as f64 - 1.0);
+            let ray = scene.camera.pinhole_ray(u, v);
+            let Some(hit) = scene.world.shoot_ray(&ray, Interval::new(1e-3, f64::INFINITY)) else {
+                continue;
+            };
+
+            let blur = scene.camera.blur(hit.p) * height as f64;
+            let amount = match blur {
+                b if b < 0.25 => 0.75,
+                b if b < 1.0 => 0.4,
+                _ => continue,
+            };
+            let Rgb(pixel) = img.get_pixel_mut(x, y);
+            for (channel, tint) in pixel.iter_mut().zip([40.0, 255.0, 80.0]) {
+                *channel = (*channel as f64 * (1.0 - amount) + tint * amount) as u8;
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '|' => [0b010, 0b010, 0b010, 0b010, 0b010],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
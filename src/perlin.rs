@@ -0,0 +1,75 @@
+use crate::vec3::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise: a random unit vector on every point of an integer lattice,
+/// blended over the cells in between, so it's smooth and repeats only every 256 units
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+impl Perlin {
+    /// The same seed always gives the same noise, no matter the render's seed
+    pub fn new(seed: u64) -> Self {
+        let rng = fastrand::Rng::with_seed(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vec3::new(rng.f64() * 2.0 - 1.0, rng.f64() * 2.0 - 1.0, rng.f64() * 2.0 - 1.0).unit_vec())
+            .collect();
+
+        let permutation = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            rng.shuffle(&mut perm);
+            perm
+        };
+        Self { gradients, perm_x: permutation(), perm_y: permutation(), perm_z: permutation() }
+    }
+
+    /// Noise at `p`, in -1.0..=1.0 (mostly well inside it)
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // Hermite smoothing, so the lattice doesn't show as creases
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        // Trilinear blend of the 8 corners' gradients, each dotted with the offset to it
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let wrap = |coord: i64, d: i64| ((coord + d) & (POINT_COUNT as i64 - 1)) as usize;
+                    let hash = self.perm_x[wrap(i, di)] ^ self.perm_y[wrap(j, dj)] ^ self.perm_z[wrap(k, dk)];
+
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * self.gradients[hash].dot(&weight);
+                }
+            }
+        }
+
+        sum
+    }
+
+    /// Sum of `depth` octaves of noise, each twice the frequency and half the weight
+    /// of the one before, folded to positive: the look of turbulent mixing
+    pub fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+
+        sum.abs()
+    }
+}
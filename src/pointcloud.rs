@@ -0,0 +1,160 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::hit::Hit;
+use crate::interval::Interval;
+use crate::material::*;
+use crate::sphere::{self, Sphere};
+use crate::vec3::{Point3, Vec3};
+
+/// Max amount of points in a leaf of the point cloud's bounding volume tree
+const LEAF_SIZE: usize = 4;
+
+/// Node of a flattened bounding volume tree over the points
+struct Node {
+    bbox: Aabb,
+    kind: NodeKind
+}
+
+enum NodeKind {
+    /// Range of `PointCloud::order` holding the points inside this leaf
+    Leaf(Range<usize>),
+    /// The left child is always the node right after its parent, so only
+    /// the index of the right one needs to be stored
+    Inner { right: usize }
+}
+
+/// A (possibly huge) set of points rendered as tiny spheres sharing one radius.
+/// Points are indexed by a bounding volume tree built on construction so each
+/// ray only tests the handful of points along its way.
+pub struct PointCloud {
+    points: Vec<Point3>,
+    colors: Vec<Color>,
+    radius: f64,
+    material: Arc<dyn Material>,
+    order: Vec<usize>,
+    nodes: Vec<Node>
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<Point3>, radius: f64, material: Arc<dyn Material>) -> Self {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        let mut nodes = Vec::with_capacity(2 * points.len() / LEAF_SIZE + 1);
+        if !points.is_empty() {
+            PointCloud::build(&points, radius, &mut order, 0, &mut nodes);
+        }
+
+        Self { points, colors: Vec::new(), radius, material, order, nodes }
+    }
+
+    /// Shade every point as a diffuse sphere with its own albedo, instead of
+    /// sharing the cloud's material. Needs exactly one color per point.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(colors.len(), self.points.len(), "Need exactly one color per point");
+        self.colors = colors;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Recursively split `order` at the median of its widest axis, pushing the
+    /// nodes in depth-first order. `offset` is where `order` starts in the full array.
+    fn build(points: &[Point3], radius: f64, order: &mut [usize], offset: usize, nodes: &mut Vec<Node>) {
+        let padding = Vec3::new(radius, radius, radius);
+        let (mut min, mut max) = (points[order[0]], points[order[0]]);
+        for &idx in order.iter() {
+            min = min.min(&points[idx]);
+            max = max.max(&points[idx]);
+        }
+
+        let node_idx = nodes.len();
+        nodes.push(Node {
+            bbox: Aabb::new(min - padding, max + padding),
+            kind: NodeKind::Leaf(offset..offset + order.len())
+        });
+
+        if order.len() <= LEAF_SIZE {
+            return;
+        }
+
+        let axis = Aabb::new(min, max).longest_axis();
+
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+        let (left, right) = order.split_at_mut(mid);
+
+        PointCloud::build(points, radius, left, offset, nodes);
+        let right_idx = nodes.len();
+        PointCloud::build(points, radius, right, offset + mid, nodes);
+
+        nodes[node_idx].kind = NodeKind::Inner { right: right_idx };
+    }
+}
+
+impl Hit for PointCloud {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut interval = *interval;
+        let mut closest = None;
+
+        // Depth-first traversal with an explicit stack - the tree is balanced, so
+        // 64 levels are plenty for any amount of points that fits in memory.
+        let mut stack = [0usize; 64];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_idx = stack[stack_len];
+            let node = &self.nodes[node_idx];
+            if !node.bbox.hit(ray, &interval) {
+                continue;
+            }
+
+            match &node.kind {
+                NodeKind::Leaf(range) => {
+                    for &idx in &self.order[range.clone()] {
+                        if let Some(t) = sphere::intersect(&self.points[idx], self.radius, ray, &interval) {
+                            interval.max = t;
+                            closest = Some((idx, t));
+                        }
+                    }
+                },
+                NodeKind::Inner { right } => {
+                    stack[stack_len] = *right;
+                    stack[stack_len + 1] = node_idx + 1;
+                    stack_len += 2;
+                }
+            }
+        }
+
+        let (idx, t) = closest?;
+        let p = ray.at(t);
+        let outward_normal = (p - self.points[idx]) / self.radius;
+
+        // Per point colors are rare enough (only on hits) that making the
+        // material on the fly beats keeping millions of them around.
+        let material: Arc<dyn Material> = match self.colors.get(idx) {
+            Some(&albedo) => Arc::new(Lambertian::new(albedo)),
+            None => Arc::clone(&self.material),
+        };
+
+        Some(HitRecord::new(t, p, outward_normal, Sphere::uv(&outward_normal), ray, material))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bbox,
+            None => Aabb::new(Point3::default(), Point3::default()),
+        }
+    }
+}
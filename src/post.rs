@@ -0,0 +1,92 @@
+use crate::color::Color;
+use crate::film::{Film, PostProcess};
+
+use rayon::prelude::*;
+
+/// Bloom/glare: real lenses (and eyes) scatter a small part of the light reaching
+/// them all over the image, which only shows around things much brighter than
+/// their surroundings. Modelled as spreading `strength` of every pixel's light
+/// with a sum of gaussians, the wide ones giving the faint glare around the glow.
+/// Runs on the HDR film, so bright emitters and glints glow instead of clipping
+/// to flat white. Away from the edges the total amount of light stays the same,
+/// near them it doesn't quite (see gaussian_blur).
+#[derive(Clone, Copy)]
+pub struct Bloom {
+    strength: f64,
+    radius: f64
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self { strength: 0.04, radius: 0.02 }
+    }
+}
+
+impl Bloom {
+    /// `radius` is the spread of the widest gaussian, as a fraction of the
+    /// film's width (so the look doesn't depend on the resolution)
+    pub fn new(strength: f64, radius: f64) -> Self {
+        Self { strength: strength.clamp(0.0, 1.0), radius }
+    }
+}
+
+impl PostProcess for Bloom {
+    fn pass_done(&self, film: &mut Film) {
+        // (fraction of the widest spread, weight) - most of the scattered light
+        // stays close, a long tail reaches further out
+        const LOBES: [(f64, f64); 3] = [(0.1, 0.6), (0.35, 0.3), (1.0, 0.1)];
+
+        let spread = self.radius * film.width() as f64;
+        let mut scattered = vec![Color::default(); film.pixels().len()];
+        for (fraction, weight) in LOBES {
+            let blurred = gaussian_blur(film, (fraction * spread).max(0.5));
+            scattered.par_iter_mut().zip(&blurred).for_each(|(sum, blurred)| *sum += weight * *blurred);
+        }
+
+        film.pixels_mut().par_iter_mut().zip(&scattered).for_each(|(pixel, scattered)| {
+            *pixel = (1.0 - self.strength) * *pixel + self.strength * *scattered;
+        });
+    }
+}
+
+/// Separable gaussian blur. Taps falling outside the film are dropped and the
+/// rest renormalized, so the edges don't darken. That's an average per output
+/// pixel rather than spreading each input pixel's light, so near the edges light
+/// isn't conserved: pixels there count for more than their share.
+fn gaussian_blur(film: &Film, sigma: f64) -> Vec<Color> {
+    let (width, height) = (film.width(), film.height());
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|k| (-((k * k) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    // Weighted average of the taps around `center` that land inside 0..len
+    let convolve = |center: usize, len: usize, at: &dyn Fn(usize) -> Color| {
+        let (mut sum, mut total) = (Color::default(), 0.0);
+        for (k, weight) in (-radius..=radius).zip(&kernel) {
+            let idx = center as isize + k;
+            if idx >= 0 && (idx as usize) < len {
+                sum += *weight * at(idx as usize);
+                total += weight;
+            }
+        }
+        sum / total
+    };
+
+    let pixels = film.pixels();
+    let mut horizontal = vec![Color::default(); pixels.len()];
+    horizontal.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = convolve(x, width, &|x| pixels[y * width + x]);
+        }
+    });
+
+    let mut blurred = vec![Color::default(); pixels.len()];
+    blurred.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = convolve(y, height, &|y| horizontal[y * width + x]);
+        }
+    });
+
+    blurred
+}
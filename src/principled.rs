@@ -0,0 +1,199 @@
+use std::f64::consts::PI;
+
+use crate::color::{self, Color};
+use crate::material::{HitRecord, Material, Ray, Scatter};
+use crate::microfacet::{self, Ggx, Gtr1};
+use crate::onb::Onb;
+use crate::ray::Lobe;
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// The clearcoat is a colorless lacquer, reflecting 4% head on
+const CLEARCOAT_F0: Color = Color { x: 0.04, y: 0.04, z: 0.04 };
+
+/// Disney's principled BRDF (Burley 2012): one material whose handful of artist
+/// friendly parameters, all in 0.0..=1.0, cover most surfaces. Built from a
+/// diffuse base with retro-reflection (blending into a subsurface look-alike)
+/// and sheen, a GGX specular lobe and a GTR1 clearcoat layer on top.
+pub struct Principled {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    sheen: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    subsurface: f64
+}
+
+impl Principled {
+    /// A rough-ish dielectric, with 4% reflectance like most of them
+    pub fn new(base_color: Color) -> Self {
+        Self {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            subsurface: 0.0
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: f64) -> Self {
+        self.metallic = metallic.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Amount of specular reflection of dielectrics, 0.5 is 4% head on
+    pub fn with_specular(mut self, specular: f64) -> Self {
+        self.specular = specular.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Extra reflection at grazing angles, for cloth
+    pub fn with_sheen(mut self, sheen: f64) -> Self {
+        self.sheen = sheen.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Strength of a clear, colorless coat on top, and how glossy it is
+    pub fn with_clearcoat(mut self, clearcoat: f64, gloss: f64) -> Self {
+        self.clearcoat = clearcoat.clamp(0.0, 1.0);
+        self.clearcoat_gloss = gloss.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Blend the diffuse lobe towards a flatter, subsurface scattering look
+    pub fn with_subsurface(mut self, subsurface: f64) -> Self {
+        self.subsurface = subsurface.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Specular reflectance head on, dielectrics' from `specular` and metals' their color
+    fn f0(&self) -> Color {
+        let dielectric_f0 = 0.08 * self.specular * color::WHITE;
+        (1.0 - self.metallic) * dielectric_f0 + self.metallic * self.base_color
+    }
+
+    /// The clearcoat's distribution, glossier coats are narrower
+    fn gtr1(&self) -> Gtr1 {
+        Gtr1::new(0.1 + (0.001 - 0.1) * self.clearcoat_gloss)
+    }
+
+    /// The base color with its luminance taken out
+    fn tint(&self) -> Color {
+        let luminance = 0.3 * self.base_color.x + 0.6 * self.base_color.y + 0.1 * self.base_color.z;
+        match luminance > 0.0 {
+            true => self.base_color / luminance,
+            false => color::WHITE,
+        }
+    }
+
+    /// Diffuse and sheen, times cos(wi) / pdf for a cosine sampled wi
+    fn diffuse(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let h = (*wo + *wi).unit_vec();
+        let cos_d = wi.dot(&h);
+        let (fl, fv) = (microfacet::schlick_weight(wi.z), microfacet::schlick_weight(wo.z));
+
+        // Burley's diffuse, rough surfaces get brighter at grazing angles
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+
+        // Hanrahan-Krueger inspired approximation of subsurface scattering
+        let fss90 = self.roughness * cos_d * cos_d;
+        let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+        let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+
+        let sheen_color = 0.5 * color::WHITE + 0.5 * self.tint();
+        let sheen = PI * self.sheen * microfacet::schlick_weight(cos_d) * sheen_color;
+
+        let diffuse = ((1.0 - self.subsurface) * fd + self.subsurface * ss) * self.base_color;
+        (1.0 - self.metallic) * (diffuse + sheen)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, hit: &HitRecord, ray: &Ray) -> Option<Scatter> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.direction.unit_vec());
+        if wo.z <= 0.0 {
+            return None;    // Only possible with shading normals, seen from behind
+        }
+
+        let ggx = Ggx::new(self.roughness);
+        let f0 = self.f0();
+
+        // Pick one lobe, roughly in proportion to how much it reflects, and divide its
+        // weight by the chance of picking it so that the sum of all lobes is unbiased
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let weights = [
+            (1.0 - self.metallic) * (average(self.base_color) + self.sheen),
+            average(microfacet::schlick(f0, wo.z)),
+            0.25 * self.clearcoat * average(microfacet::schlick(CLEARCOAT_F0, wo.z)),
+        ];
+        let total: f64 = weights.iter().sum();
+        let mut pick = fastrand::f64() * total;
+        let lobe = weights.iter().position(|&weight| {
+            pick -= weight;
+            pick < 0.0
+        }).unwrap_or(1);
+        let chance = match total > 0.0 {
+            true => weights[lobe] / total,
+            false => 1.0,
+        };
+
+        let (wi, attenuation) = match lobe {
+            0 => {
+                let wi = microfacet::sample_cosine();
+                (wi, self.diffuse(&wo, &wi))
+            },
+            1 => {
+                let h = ggx.sample_visible_normal(&wo, sampler::glossy_2d());
+                let wi = (-wo).reflect(&h);
+                let fresnel = microfacet::schlick(f0, wo.dot(&h));
+                (wi, fresnel * ggx.g2(&wo, &wi) / ggx.g1(&wo))
+            },
+            _ => {
+                // D * cos(h) sampling: the D terms cancel out of the weight
+                let h = self.gtr1().sample_normal(sampler::glossy_2d());
+                let wi = (-wo).reflect(&h);
+                let fresnel = microfacet::schlick(CLEARCOAT_F0, wo.dot(&h));
+                let masking = Ggx::new(0.5).g2(&wo, &wi);
+                (wi, 0.25 * self.clearcoat * masking * wo.dot(&h) / (wo.z * h.z) * fresnel)
+            },
+        };
+
+        // Sheen rides on the diffuse lobe, the specular and clearcoat ones are reflections
+        let kind = match lobe {
+            0 => Lobe::Diffuse,
+            _ => Lobe::Specular,
+        };
+        match wi.z > 0.0 {
+            true => Some((Ray::new(hit.p, frame.to_world(&wi)).with_lobe(kind), attenuation / chance)),
+            false => None,
+        }
+    }
+
+    fn lit(&self, hit: &HitRecord, ray: &Ray, to_light: &Vec3) -> Color {
+        let frame = Onb::from_w(&hit.normal);
+        let (wo, wi) = (frame.to_local(&-ray.direction.unit_vec()), frame.to_local(to_light));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return color::BLACK;
+        }
+
+        let h = (wo + wi).unit_vec();
+        // `diffuse` is already over the cosine sampling's pdf of cos / π
+        let diffuse = wi.z / PI * self.diffuse(&wo, &wi);
+        let specular = Ggx::new(self.roughness).reflected(&wo, &wi) * microfacet::schlick(self.f0(), wo.dot(&h));
+        let masking = Ggx::new(0.5).g2(&wo, &wi);
+        let clearcoat = 0.25 * self.clearcoat * self.gtr1().d(&h) * masking / (4.0 * wo.z);
+        diffuse + specular + clearcoat * microfacet::schlick(CLEARCOAT_F0, wo.dot(&h))
+    }
+}
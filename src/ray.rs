@@ -3,12 +3,34 @@ use crate::vec3::{Vec3, Point3};
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
-    pub direction: Vec3
+    pub direction: Vec3,
+    /// Color channel (0 red, 1 green, 2 blue) the ray carries alone since dispersion
+    /// split it, None while it carries all of them. Scattered rays inherit it.
+    pub channel: Option<usize>,
+    /// How the surface the ray leaves scattered it
+    pub lobe: Lobe
+}
+
+/// Kind of scattering that made a ray, to split renders by it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lobe {
+    /// Diffuse and everything rough enough to count as it (cloth, volumes)
+    #[default]
+    Diffuse,
+    /// Mirror like or glossy reflection
+    Specular,
+    /// Refraction into or out of a medium
+    Transmission
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self { origin, direction, channel: None, lobe: Lobe::Diffuse }
+    }
+
+    pub fn with_lobe(mut self, lobe: Lobe) -> Self {
+        self.lobe = lobe;
+        self
     }
 
     pub fn at(&self, t: f64) -> Point3 {
@@ -0,0 +1,143 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hit::Hit;
+use crate::interval::Interval;
+use crate::material::*;
+use crate::vec3::{Point3, Vec3};
+
+/// Thickness given to the bounding box of the rects along their flat axis
+const RECT_PADDING: f64 = 1e-4;
+
+/// Axis-aligned rectangle on the plane z = k, spanning x0..x1 and y0..y1
+#[derive(Clone)]
+pub struct XyRect {
+    x: Interval,
+    y: Interval,
+    k: f64,
+    material: Arc<dyn Material>
+}
+
+impl XyRect {
+    pub fn new(x: Range<f64>, y: Range<f64>, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { x: x.into(), y: y.into(), k, material }
+    }
+}
+
+impl Hit for XyRect {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let t = (self.k - ray.origin.z) / ray.direction.z;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if !self.x.contains(p.x) || !self.y.contains(p.y) {
+            return None;
+        }
+
+        let u = (p.x - self.x.min) / (self.x.max - self.x.min);
+        let v = (p.y - self.y.min) / (self.y.max - self.y.min);
+
+        let outward_normal = Vec3::new(0.0, 0.0, 1.0);
+        let hit = HitRecord::new(t, p, outward_normal, (u, v), ray, Arc::clone(&self.material));
+        Some(hit.with_tangent(Vec3::new(1.0, 0.0, 0.0)))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::new(
+            Point3::new(self.x.min, self.y.min, self.k),
+            Point3::new(self.x.max, self.y.max, self.k)
+        );
+        bbox.padded(RECT_PADDING)
+    }
+}
+
+/// Axis-aligned rectangle on the plane y = k, spanning x0..x1 and z0..z1
+#[derive(Clone)]
+pub struct XzRect {
+    x: Interval,
+    z: Interval,
+    k: f64,
+    material: Arc<dyn Material>
+}
+
+impl XzRect {
+    pub fn new(x: Range<f64>, z: Range<f64>, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { x: x.into(), z: z.into(), k, material }
+    }
+}
+
+impl Hit for XzRect {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let t = (self.k - ray.origin.y) / ray.direction.y;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if !self.x.contains(p.x) || !self.z.contains(p.z) {
+            return None;
+        }
+
+        let u = (p.x - self.x.min) / (self.x.max - self.x.min);
+        let v = (p.z - self.z.min) / (self.z.max - self.z.min);
+
+        let outward_normal = Vec3::new(0.0, 1.0, 0.0);
+        let hit = HitRecord::new(t, p, outward_normal, (u, v), ray, Arc::clone(&self.material));
+        Some(hit.with_tangent(Vec3::new(1.0, 0.0, 0.0)))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::new(
+            Point3::new(self.x.min, self.k, self.z.min),
+            Point3::new(self.x.max, self.k, self.z.max)
+        );
+        bbox.padded(RECT_PADDING)
+    }
+}
+
+/// Axis-aligned rectangle on the plane x = k, spanning y0..y1 and z0..z1
+#[derive(Clone)]
+pub struct YzRect {
+    y: Interval,
+    z: Interval,
+    k: f64,
+    material: Arc<dyn Material>
+}
+
+impl YzRect {
+    pub fn new(y: Range<f64>, z: Range<f64>, k: f64, material: Arc<dyn Material>) -> Self {
+        Self { y: y.into(), z: z.into(), k, material }
+    }
+}
+
+impl Hit for YzRect {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let t = (self.k - ray.origin.x) / ray.direction.x;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if !self.y.contains(p.y) || !self.z.contains(p.z) {
+            return None;
+        }
+
+        let u = (p.y - self.y.min) / (self.y.max - self.y.min);
+        let v = (p.z - self.z.min) / (self.z.max - self.z.min);
+
+        let outward_normal = Vec3::new(1.0, 0.0, 0.0);
+        let hit = HitRecord::new(t, p, outward_normal, (u, v), ray, Arc::clone(&self.material));
+        Some(hit.with_tangent(Vec3::new(0.0, 1.0, 0.0)))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::new(
+            Point3::new(self.k, self.y.min, self.z.min),
+            Point3::new(self.k, self.y.max, self.z.max)
+        );
+        bbox.padded(RECT_PADDING)
+    }
+}
@@ -0,0 +1,503 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::camera::Camera;
+use crate::color::{self, Color, ColorSum};
+use crate::control::RenderControl;
+use crate::cubemap::{self, CubeLayout};
+use crate::film::{Film, PostProcess, Region};
+use crate::hit::{self, PathLimits, Traced};
+use crate::interval::Interval;
+use crate::ray::Lobe;
+use crate::sampler;
+use crate::scenes::Scene;
+use crate::stats;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Mixed into a pixel's seed to try it again after a panic, with other random numbers
+const RETRY_SEED: u64 = 0x5851f42d4c957f2d;
+
+/// Renders scenes onto a Film, running the post processing hooks as tiles
+/// (currently single rows) and passes complete.
+pub struct Renderer {
+    width: usize,
+    height: usize,
+    samples: usize,
+    /// Overrides of the scene's own path limits
+    max_bounces: Option<u32>,
+    min_throughput: Option<f64>,
+    seed: u64,
+    stratify: bool,
+    post: Vec<Box<dyn PostProcess>>,
+    control: Option<Arc<RenderControl>>,
+    /// Region given every sample and how many times fewer everything else gets
+    focus: Option<(Region, usize)>,
+    /// The only part of the image rendered, None for all of it
+    crop: Option<Region>,
+    passes: Vec<Pass>,
+    integrator: Integrator
+}
+
+/// How camera rays' light is found
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Full path tracing, the default
+    Path,
+    /// Fast approximation for previews: lights and the sky after one diffuse bounce,
+    /// occluded by whatever is closer than `ao_distance` (see `HittableList::trace_preview`)
+    Preview { ao_distance: f64 }
+}
+
+/// Part of the render's light rendered on its own film too, so compositors can
+/// rebalance it without rendering again. Passes of one split add up to the film
+/// before post processing: direct + indirect, or emission + diffuse + specular +
+/// transmission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Straight from emitters and the sky, or off the first surface hit
+    Direct,
+    /// Everything that bounced more
+    Indirect,
+    /// Emitters and the sky seen right from the camera
+    Emission,
+    /// Light that came in through the first surface's diffuse lobe
+    Diffuse,
+    /// Through its reflection, mirror like or glossy
+    Specular,
+    /// Through refraction, what's seen through glass and water
+    Transmission
+}
+
+impl Pass {
+    pub const LIGHT: [Pass; 2] = [Pass::Direct, Pass::Indirect];
+    pub const LOBES: [Pass; 4] = [Pass::Emission, Pass::Diffuse, Pass::Specular, Pass::Transmission];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Direct => "direct",
+            Pass::Indirect => "indirect",
+            Pass::Emission => "emission",
+            Pass::Diffuse => "diffuse",
+            Pass::Specular => "specular",
+            Pass::Transmission => "transmission",
+        }
+    }
+
+    /// This pass's share of a camera ray's light
+    fn of(self, traced: &Traced) -> Color {
+        let through = |lobe: Lobe| match traced.lobe == Some(lobe) {
+            true => traced.total - traced.emission,
+            false => color::BLACK,
+        };
+        match self {
+            Pass::Direct => traced.direct,
+            Pass::Indirect => traced.total - traced.direct,
+            Pass::Emission => traced.emission,
+            Pass::Diffuse => through(Lobe::Diffuse),
+            Pass::Specular => through(Lobe::Specular),
+            Pass::Transmission => through(Lobe::Transmission),
+        }
+    }
+}
+
+/// What a render produced
+pub struct Rendered {
+    pub film: Film,
+    /// Per pixel traversal work, only filled in when built with the "stats" feature
+    pub heat: Vec<u64>,
+    /// How many pixels panicked twice (and were painted magenta)
+    pub panicked: usize,
+    /// One film per pass asked for with `Renderer::with_passes`, in that order
+    pub passes: Vec<(Pass, Film)>
+}
+
+impl Renderer {
+    pub fn new(width: usize, height: usize, samples: usize) -> Self {
+        Self { width, height, samples, max_bounces: None, min_throughput: None, seed: 0, stratify: true, post: Vec::new(), control: None, focus: None, crop: None, passes: Vec::new(), integrator: Integrator::Path }
+    }
+
+    /// Cut paths after this many bounces instead of the scene's default
+    pub fn with_max_bounces(mut self, max_bounces: u32) -> Self {
+        self.max_bounces = Some(max_bounces);
+        self
+    }
+
+    /// Start russian roulette below this throughput instead of the scene's default
+    /// (see PathLimits)
+    pub fn with_min_throughput(mut self, min_throughput: f64) -> Self {
+        self.min_throughput = Some(min_throughput);
+        self
+    }
+
+    /// Seed mixed into every pixel's rng seed, different seeds give different noise
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Whether a pixel's samples are spread evenly over the first glossy lobe they
+    /// hit (on by default), see the sampler module
+    pub fn with_stratification(mut self, stratify: bool) -> Self {
+        self.stratify = stratify;
+        self
+    }
+
+    /// Add a post processing hook, run after the ones already added
+    pub fn with_post_process(mut self, hook: impl PostProcess + 'static) -> Self {
+        self.post.push(Box::new(hook));
+        self
+    }
+
+    /// Let another thread pause, throttle or cancel renders through `control`.
+    /// Pixels skipped by a cancel are left black.
+    pub fn with_control(mut self, control: Arc<RenderControl>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Spend the sample budget where it matters while iterating on part of the
+    /// image: pixels in `region` get every sample, the rest `reduction` times fewer
+    /// (at least one). The region is in pixels of the full resolution film.
+    pub fn with_focus(mut self, region: Region, reduction: usize) -> Self {
+        self.focus = Some((region, reduction.max(1)));
+        self
+    }
+
+    /// Render only `region` (in pixels of the full resolution film) onto a film of
+    /// its size. Rays and rng seeds go by where pixels are in the whole image, so
+    /// they come out exactly as in a full render with the same settings, ready to
+    /// patch into it.
+    pub fn with_crop(mut self, region: Region) -> Self {
+        let x = region.x.start.min(self.width - 1)..region.x.end.min(self.width);
+        let y = region.y.start.min(self.height - 1)..region.y.end.min(self.height);
+        self.crop = Some(Region { x: x.start..x.end.max(x.start + 1), y: y.start..y.end.max(y.start + 1) });
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Also render these passes, each on its own film
+    pub fn with_passes(mut self, passes: &[Pass]) -> Self {
+        for &pass in passes {
+            if !self.passes.contains(&pass) {
+                self.passes.push(pass);
+            }
+        }
+        self
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn render(&self, scene: &Scene) -> Rendered {
+        self.render_at(scene, &scene.camera, (self.width, self.height), self.samples)
+    }
+
+    /// Render coarse to fine, so something recognizable shows up within moments
+    /// even for heavy scenes: passes at 1/8, 1/4 and 1/2 of the resolution with
+    /// 1, 2 and 4 samples come first, each handed to `preview` upscaled to full size
+    /// and blended with the ones before (weighted by their samples) to tame the
+    /// noise. Then the full render, returned as usual, which doesn't include them.
+    pub fn render_progressive(&self, scene: &Scene, mut preview: impl FnMut(&Film)) -> Rendered {
+        let full = self.crop_at((self.width, self.height));
+        let mut base: Option<(Film, f64)> = None;
+        for (factor, samples) in [(8, 1), (4, 2), (2, 4)] {
+            let (width, height) = (self.width / factor, self.height / factor);
+            let samples = samples.min(self.samples);
+            if width < 2 || height < 2 || samples == self.samples {
+                continue;
+            }
+
+            let pass = self.render_at(scene, &scene.camera, (width, height), samples).film.resized(full.x.len(), full.y.len());
+            let blended = match base.take() {
+                Some((mut base, weight)) => {
+                    let total = weight + samples as f64;
+                    for (base, pass) in base.pixels_mut().iter_mut().zip(pass.pixels()) {
+                        *base = (weight * *base + samples as f64 * *pass) / total;
+                    }
+                    (base, total)
+                },
+                None => (pass, samples as f64),
+            };
+
+            preview(&blended.0);
+            base = Some(blended);
+        }
+
+        self.render(scene)
+    }
+
+    /// Focus stacking, for everything in focus like macro photographers do: `shots`
+    /// renders focused at steps from the nearest to the farthest things in view, each
+    /// pixel kept from the one focused closest to it. Whatever's beyond the range
+    /// (the sky, a far background) keeps the farthest shot's bokeh.
+    pub fn render_focus_stack(&self, scene: &Scene, shots: usize) -> Rendered {
+        let depths = self.depth_map(scene);
+        let mut sorted: Vec<f64> = depths.iter().flatten().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        if sorted.is_empty() || shots < 2 {
+            return self.render(scene);
+        }
+
+        // The extremes are usually a sliver of ground at the horizon or right under
+        // the camera, not worth a shot each. Steps are even in 1 / distance, like blur.
+        let near = sorted[sorted.len() / 20].max(1e-3);
+        let far = sorted[sorted.len() * 19 / 20].max(near);
+        let focus: Vec<f64> = (0..shots)
+            .map(|shot| 1.0 / (1.0 / near + (1.0 / far - 1.0 / near) * shot as f64 / (shots - 1) as f64))
+            .collect();
+
+        let aperture = scene.camera.lens().0;
+        let rendered: Vec<Rendered> = focus.iter()
+            .map(|&distance| {
+                eprintln!("Focus stack: shot at {:.3}", distance);
+                self.render_at(scene, &scene.camera.clone().with_lens(aperture, distance), (self.width, self.height), self.samples)
+            })
+            .collect();
+
+        // Blur grows with the difference in 1 / distance, so that's what sharpest means.
+        // Escaped rays are infinitely far: 1 / distance of 0.
+        let mut stacked = Rendered {
+            film: rendered[0].film.clone(),
+            heat: vec![0; rendered[0].heat.len()],
+            panicked: rendered.iter().map(|shot| shot.panicked).sum(),
+            passes: rendered[0].passes.clone(),
+        };
+        let width = stacked.film.width();
+        for (idx, depth) in depths.iter().enumerate() {
+            let inverse = depth.map_or(0.0, |depth| 1.0 / depth);
+            let sharpest = (0..shots)
+                .min_by(|&a, &b| (1.0 / focus[a] - inverse).abs().total_cmp(&(1.0 / focus[b] - inverse).abs()))
+                .unwrap();
+
+            let (x, y) = (idx % width, idx / width);
+            let shot = &rendered[sharpest];
+            *stacked.film.pixel_mut(x, y) = shot.film.pixel(x, y);
+            for ((_, pass), (_, shot_pass)) in stacked.passes.iter_mut().zip(&shot.passes) {
+                *pass.pixel_mut(x, y) = shot_pass.pixel(x, y);
+            }
+        }
+        for shot in &rendered {
+            stacked.heat.iter_mut().zip(&shot.heat).for_each(|(total, heat)| *total += heat);
+        }
+
+        stacked
+    }
+
+    /// Everything around the camera's position as a cube map, the six faces laid
+    /// out in one image: an environment map of the scene, i.e. to light other
+    /// scenes with. Faces are a quarter of the width across, whatever the height.
+    pub fn render_cube_map(&self, scene: &Scene, layout: CubeLayout) -> Rendered {
+        let size = (self.width / 4).max(2);
+        let center = scene.camera.pinhole_ray(0.5, 0.5).origin;
+        let faces: Vec<Rendered> = cubemap::FACES.iter()
+            .map(|face| {
+                eprintln!("Cube map: face {}", face.name);
+                self.render_at(scene, &Camera::cube_face(center, face, size), (size, size), self.samples)
+            })
+            .collect();
+
+        let films = |film: &dyn Fn(&Rendered) -> &Film| layout.assemble(&faces.iter().map(film).collect::<Vec<_>>());
+        let (width, height) = layout.size(size);
+        let heat = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| match layout.nearest((x, y), size) {
+                Some((idx, fx, fy)) => faces[idx].heat[fy * size + fx],
+                None => 0,
+            })
+            .collect();
+
+        Rendered {
+            film: films(&|face| &face.film),
+            heat,
+            panicked: faces.iter().map(|face| face.panicked).sum(),
+            passes: self.passes.iter()
+                .enumerate()
+                .map(|(idx, &pass)| (pass, films(&|face| &face.passes[idx].1)))
+                .collect(),
+        }
+    }
+
+    /// Depth (along the view direction) of what's seen through each pixel's center,
+    /// None where it's the sky. In the film's order, so covering only the crop.
+    fn depth_map(&self, scene: &Scene) -> Vec<Option<f64>> {
+        let crop = self.crop_at((self.width, self.height));
+        let (width, height) = (self.width, self.height);
+        let interval = Interval::new(scene.world.epsilon(&scene.camera.pinhole_ray(0.5, 0.5).origin, 0.0), f64::INFINITY);
+
+        crop.y.clone()
+            .flat_map(|y| crop.x.clone().map(move |x| (x, y)))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|(i, y)| {
+                let j = height - 1 - y;
+                let u = (i as f64 + 0.5) / (width as f64 - 1.0);
+                let v = (j as f64 + 0.5) / (height as f64 - 1.0);
+                let hit = scene.world.shoot_ray(&scene.camera.pinhole_ray(u, v), interval)?;
+                Some(scene.camera.depth(hit.p))
+            })
+            .collect()
+    }
+
+    /// Render at any resolution and sample count through `cam`, with everything else as configured
+    fn render_at(&self, scene: &Scene, cam: &Camera, (width, height): (usize, usize), samples: usize) -> Rendered {
+        let world = &scene.world;
+        let limits = PathLimits {
+            max_bounces: self.max_bounces.unwrap_or(scene.limits.max_bounces),
+            min_throughput: self.min_throughput.unwrap_or(scene.limits.min_throughput),
+        };
+
+        // Pixel (i, y) of the image is (i - crop.x.start, y - crop.y.start) on the film
+        let crop = self.crop_at((width, height));
+        let (film_width, film_height) = (crop.x.len(), crop.y.len());
+
+        let mut film = Film::new(film_width, film_height);
+        let mut heat = vec![0u64; film_width * film_height];
+        let mut passes: Vec<(Pass, Film)> = self.passes.iter().map(|&pass| (pass, Film::new(film_width, film_height))).collect();
+        let panicked = AtomicUsize::new(0);
+
+        // Rendering main loop - iterate over rows and pixels then shoot our rays
+        for y in crop.y.clone() {
+            let j = height - 1 - y;
+            eprintln!("On row {}", j);
+
+            // Pixels come back in order, None for those skipped by a cancel
+            let row: Vec<Option<(Color, u64, Vec<Color>)>> = crop.x.clone().into_par_iter().map(|i| {
+                // Waits while paused or throttled, held until the pixel is done
+                let _running = match self.control.as_deref().map(RenderControl::start_pixel) {
+                    Some(None) => return None,   // Cancelled
+                    Some(running) => running,
+                    None => None,
+                };
+
+                // A panic (bad geometry, NaN asserts, ...) only costs us this pixel: it's
+                // tried once more with other random numbers, in case it was a rare path,
+                // then painted in magenta so it's easy to spot, and the render goes on.
+                let samples = self.samples_at((i, y), (width, height), samples);
+                let attempt = |seed: u64| {
+                    hit::take_last_hit();
+                    let pixel = panic::catch_unwind(AssertUnwindSafe(|| {
+                        let mut pixel = ColorSum::default();
+                        let mut pass_sums = vec![ColorSum::default(); self.passes.len()];
+
+                        // Every random number used by materials comes from the thread local
+                        // rng, so seeding it per pixel makes the samples independent of which
+                        // thread (or in which order) the pixel ends up being rendered.
+                        fastrand::seed(seed);
+                        for sample in 0..samples {
+                            sampler::start_sample(sample, samples, self.stratify);
+
+                            let u = (i as f64 + fastrand::f64()) / (width as f64 - 1.0);
+                            let v = (j as f64 + fastrand::f64()) / (height as f64 - 1.0);
+
+                            let ray = cam.gen_ray(u, v);
+                            let traced = match self.integrator {
+                                Integrator::Path => world.trace(ray, limits),
+                                Integrator::Preview { ao_distance } => world.trace_preview(ray, ao_distance),
+                            };
+                            pixel += traced.total;
+                            for (sum, pass) in pass_sums.iter_mut().zip(&self.passes) {
+                                *sum += pass.of(&traced);
+                            }
+                        }
+
+                        let average = |sum: &ColorSum| sum.total() / samples as f64;
+                        (average(&pixel), pass_sums.iter().map(average).collect::<Vec<_>>())
+                    }));
+                    sampler::clear();
+
+                    // The panic message itself was already printed by the panic hook
+                    pixel.map_err(|_| {
+                        let culprit = match hit::take_last_hit() {
+                            Some(id) => match world.name(id) {
+                                Some(name) => format!(", last hit '{}'", name),
+                                None => format!(", last hit {:?}", id),
+                            },
+                            None => String::new(),
+                        };
+                        eprintln!("Pixel ({}, {}) panicked{}", i, j, culprit);
+                    })
+                };
+
+                let seed = self.pixel_seed(i, j);
+                let pixel = attempt(seed).or_else(|_| attempt(seed ^ RETRY_SEED));
+                let heat = stats::take().total();
+
+                let (pixel, passes) = pixel.unwrap_or_else(|_| {
+                    eprintln!("Pixel ({}, {}) panicked again, filling it with the error color", i, j);
+                    panicked.fetch_add(1, Ordering::Relaxed);
+                    (color::MAGENTA, vec![color::MAGENTA; self.passes.len()])
+                });
+                Some((pixel, heat, passes))
+            }).collect();
+
+            let film_y = y - crop.y.start;
+            for (film_x, pixel) in row.into_iter().enumerate() {
+                let Some((pixel, pixel_heat, pass_pixels)) = pixel else {
+                    continue;
+                };
+                *film.pixel_mut(film_x, film_y) = pixel;
+                heat[film_y * film_width + film_x] = pixel_heat;
+                for ((_, pass), value) in passes.iter_mut().zip(pass_pixels) {
+                    *pass.pixel_mut(film_x, film_y) = value;
+                }
+            }
+
+            let region = Region { x: 0..film_width, y: film_y..film_y + 1 };
+            for hook in &self.post {
+                hook.tile_done(&mut film, &region);
+            }
+        }
+
+        for hook in &self.post {
+            hook.pass_done(&mut film);
+        }
+
+        Rendered { film, heat, panicked: panicked.into_inner(), passes }
+    }
+
+    /// The crop in pixels of an image of the given size, which may be smaller than
+    /// the full resolution one it's set in. The whole image without a crop.
+    fn crop_at(&self, (width, height): (usize, usize)) -> Region {
+        let Some(crop) = &self.crop else {
+            return Region { x: 0..width, y: 0..height };
+        };
+
+        let scale = |range: &std::ops::Range<usize>, size: usize, full: usize| {
+            let start = (range.start * size / full).min(size - 1);
+            start..(range.end * size).div_ceil(full).clamp(start + 1, size)
+        };
+        Region { x: scale(&crop.x, width, self.width), y: scale(&crop.y, height, self.height) }
+    }
+
+    /// Samples for pixel (x, y) of an image of the given size, which may be smaller
+    /// than the full resolution one the focus region is in
+    fn samples_at(&self, (x, y): (usize, usize), (width, height): (usize, usize), samples: usize) -> usize {
+        match &self.focus {
+            Some((region, reduction)) => {
+                let (x, y) = (x * self.width / width, y * self.height / height);
+                match region.x.contains(&x) && region.y.contains(&y) {
+                    true => samples,
+                    false => (samples / reduction).max(1),
+                }
+            },
+            None => samples,
+        }
+    }
+
+    /// Hash the pixel coordinates (and the seed) into a well mixed rng seed
+    fn pixel_seed(&self, i: usize, j: usize) -> u64 {
+        // splitmix64 finalizer - neighbouring pixels get completely unrelated seeds
+        let mut z = self.seed ^ ((j as u64) << 32 | i as u64);
+        z = z.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
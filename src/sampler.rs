@@ -0,0 +1,104 @@
+//! Stratified samples for the first glossy scatter of every camera ray, and for
+//! where on the lens it starts.
+//!
+//! Independent random directions clump together and leave gaps, which at low
+//! sample counts shows up as noisy glossy highlights. Instead, the renderer
+//! tells us which of a pixel's samples is being traced, and the first glossy
+//! lobe sampled along its path gets a jittered point inside that sample's own
+//! cell of a grid over [0, 1)², so a pixel's samples cover the lobe evenly.
+//! Later bounces (and anything traced outside the renderer) use plain random numbers.
+
+use std::cell::Cell;
+
+/// 1 / golden ratio, the angle between consecutive points of a golden spiral as
+/// a fraction of a turn
+const GOLDEN_TURN: f64 = 0.618_033_988_749_895;
+
+thread_local! {
+    // (cell, columns, rows) waiting to be used by the current sample's path
+    static STRATUM: Cell<Option<(usize, usize, usize)>> = const { Cell::new(None) };
+    // (index, count) of the sample being traced, for the lens
+    static SAMPLE: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+    // Random per pixel, so their lens samples don't all share one pattern
+    static LENS_SHIFT: Cell<f64> = const { Cell::new(0.0) };
+}
+
+/// Start tracing sample `index` out of the pixel's `count`. The lens always gets
+/// the sample's spot, the first glossy lobe only if `stratify` is on.
+pub fn start_sample(index: usize, count: usize, stratify: bool) {
+    // Leftover samples that don't fill a row of the grid are random
+    let (columns, rows) = grid(count);
+
+    let stratum = match stratify && index < columns * rows {
+        true => Some((index, columns, rows)),
+        false => None,
+    };
+    STRATUM.with(|cell| cell.set(stratum));
+    SAMPLE.with(|cell| cell.set(Some((index, count))));
+}
+
+/// Stop stratifying, i.e: once a pixel is done
+pub fn clear() {
+    STRATUM.with(|cell| cell.set(None));
+    SAMPLE.with(|cell| cell.set(None));
+}
+
+/// 2D sample in [0, 1)² for a point on the lens: jittered in a cell of the same
+/// grid glossy lobes use, shifted by a random number of cells per pixel so the
+/// lens and lobe samples aren't paired the same way everywhere. Random outside
+/// the renderer.
+pub fn lens_2d() -> (f64, f64) {
+    let Some((index, count)) = SAMPLE.with(Cell::get) else {
+        return (fastrand::f64(), fastrand::f64());
+    };
+
+    let (columns, rows) = grid(count);
+    let cells = columns * rows;
+    match index < cells {
+        true => {
+            let cell = (index + (lens_shift(index) * cells as f64) as usize) % cells;
+            (((cell % columns) as f64 + fastrand::f64()) / columns as f64, ((cell / columns) as f64 + fastrand::f64()) / rows as f64)
+        },
+        false => (fastrand::f64(), fastrand::f64()),
+    }
+}
+
+/// Point on the lens as (radius², fraction of a turn): the sample's point of a
+/// golden angle (Vogel) spiral through all of the pixel's samples, which spreads
+/// any number of them evenly over the disk. The spiral gets a random turn per
+/// pixel. Random outside the renderer.
+pub fn spiral_2d() -> (f64, f64) {
+    let Some((index, count)) = SAMPLE.with(Cell::get) else {
+        return (fastrand::f64(), fastrand::f64());
+    };
+
+    let turn = (index as f64 * GOLDEN_TURN + lens_shift(index)).fract();
+    ((index as f64 + 0.5) / count as f64, turn)
+}
+
+/// The current pixel's random lens shift in [0, 1), picked by its first sample as
+/// they're traced in order
+fn lens_shift(index: usize) -> f64 {
+    if index == 0 {
+        LENS_SHIFT.with(|cell| cell.set(fastrand::f64()));
+    }
+    LENS_SHIFT.with(Cell::get)
+}
+
+/// The biggest grid that fits in the sample count, as (columns, rows)
+fn grid(count: usize) -> (usize, usize) {
+    let columns = (count as f64).sqrt() as usize;
+    (columns, count / columns.max(1))
+}
+
+/// 2D sample in [0, 1)² for picking a direction in a glossy lobe. Stratified for
+/// the first one of a path, random afterwards.
+pub fn glossy_2d() -> (f64, f64) {
+    match STRATUM.with(|cell| cell.take()) {
+        Some((index, columns, rows)) => {
+            let (column, row) = (index % columns, index / columns);
+            ((column as f64 + fastrand::f64()) / columns as f64, (row as f64 + fastrand::f64()) / rows as f64)
+        },
+        None => (fastrand::f64(), fastrand::f64()),
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use crate::background::Solid;
+use crate::camera::Camera;
+use crate::color::{self, Color};
+use crate::bvh::BvhNode;
+use crate::hit::{Hit, HittableList, PathLimits};
+use crate::instance::Instance;
+use crate::material::{self, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::sphere::Sphere;
+use crate::transform::Transform;
+use crate::vec3::{Point3, Vec3};
+
+/// A world to render plus the camera it's meant to be looked at from
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+    /// How far paths go in it, unless the renderer says otherwise
+    pub limits: PathLimits
+}
+
+/// The book's final scene. Spheres are placed with the thread local rng, so
+/// seed it beforehand (`fastrand::seed`) to get the same scene every time.
+pub fn random_scene(aspect_ratio: f64) -> Scene {
+    // Init empty world
+    let mut world = HittableList::default();
+
+    // Make our ground sphere
+    let albedo = Color::new(0.5, 0.5, 0.5);
+    let mat_ground = Arc::new(Lambertian::new(albedo));
+    let sphere_ground = Sphere::new(0.0, -1000.0, 0.0, 1000.0, mat_ground);
+    world.add_named("/ground", sphere_ground);
+
+    let p = Point3::new(4.0, 0.2, 0.0);
+
+    for i in -11..11 {
+        for j in -11..11 {
+            let i = i as f64;
+            let j = j as f64;
+
+            let rand_mat = fastrand::f64();
+            let center = Point3::new(i + 0.9 * fastrand::f64(), 0.2, j + 0.9 * fastrand::f64());
+
+            if (center - p).len() < 0.9 {
+                continue;
+            }
+
+            let material: Arc<dyn material::Material> = match rand_mat {
+                x if x < 0.8 => {   // Diffuse
+                    let albedo = Color::rand() * Color::rand();
+                    Arc::new(Lambertian::new(albedo))
+                },
+                x if x < 0.95 => {  // Metal
+                    let albedo = Color::rand_range(0.5..1.0);
+                    let fuzz = fastrand::f64() * 0.5; // f64 in range 0.0..0.5
+                    Arc::new(Metal::new(albedo, fuzz))
+                },
+                _ => Arc::new(Dielectric::new(1.5)) // Glass
+            };
+
+            let sphere = Sphere::new(center.x, center.y, center.z, 0.2, material);
+            world.add_named(&format!("/small_spheres/{}_{}", i, j), sphere);
+        }
+    }
+
+    // Instantiate the big glass sphere.
+    let material = Arc::new(Dielectric::new(1.5));
+    let sphere = Sphere::new(0.0, 1.0, 0.0, 1.0, material);
+    world.add_named("/big_spheres/glass", sphere);
+
+    // Instantiate the big opaque sphere.
+    let albedo = Color::new(0.4, 0.2, 0.1);
+    let material = Arc::new(Lambertian::new(albedo));
+    let sphere = Sphere::new(-4.0, 1.0, 0.0, 1.0, material);
+    world.add_named("/big_spheres/diffuse", sphere);
+
+    // Instantiate the big metallic sphere.
+    let albedo = Color::new(0.7, 0.6, 0.5);
+    let fuzz = 0.0;
+    let material = Arc::new(Metal::new(albedo, fuzz));
+    let sphere = Sphere::new(4.0, 1.0, 0.0, 1.0, material);
+    world.add_named("/big_spheres/metal", sphere);
+
+    // Instantiate Camera
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = 10.0;
+    let aperture = 0.1;
+
+    let camera = Camera::new(lookfrom, lookat, vup, 20.0, aspect_ratio, aperture, dist_to_focus);
+
+    Scene { world, camera, limits: PathLimits::DEFAULT }
+}
+
+/// The standard Cornell box: a 555 units wide room with a green left wall, red
+/// right wall and a square panel on the ceiling, seen through its open side, with
+/// a short and a tall white block turned slightly towards each other.
+pub fn cornell_box(aspect_ratio: f64) -> Scene {
+    let mut world = HittableList::default();
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    let light = Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0)));
+
+    world.add_named("/walls/left", YzRect::new(0.0..555.0, 0.0..555.0, 555.0, green));
+    world.add_named("/walls/right", YzRect::new(0.0..555.0, 0.0..555.0, 0.0, red));
+    world.add_named("/light", XzRect::new(213.0..343.0, 227.0..332.0, 554.0, light));
+    world.add_named("/walls/floor", XzRect::new(0.0..555.0, 0.0..555.0, 0.0, white.clone()));
+    world.add_named("/walls/ceiling", XzRect::new(0.0..555.0, 0.0..555.0, 555.0, white.clone()));
+    world.add_named("/walls/back", XyRect::new(0.0..555.0, 0.0..555.0, 555.0, white.clone()));
+
+    world.add_named("/blocks/short", block(Vec3::new(165.0, 165.0, 165.0), -18.0, Vec3::new(130.0, 0.0, 65.0), white.clone()));
+    world.add_named("/blocks/tall", block(Vec3::new(165.0, 330.0, 165.0), 15.0, Vec3::new(265.0, 0.0, 295.0), white));
+
+    // The room is closed except for the camera's side, only the panel lights it
+    world.set_background(Arc::new(Solid::new(color::BLACK)));
+
+    let lookfrom = Point3::new(278.0, 278.0, -800.0);
+    let lookat = Point3::new(278.0, 278.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = 10.0;
+    let aperture = 0.0;
+
+    let camera = Camera::new(lookfrom, lookat, vup, 40.0, aspect_ratio, aperture, dist_to_focus);
+
+    Scene { world, camera, limits: PathLimits::DEFAULT }
+}
+
+/// Closed box from the origin to `size`, turned `degrees` around the y axis and
+/// then moved by `offset`
+fn block(size: Vec3, degrees: f64, offset: Vec3, material: Arc<dyn Material>) -> Instance {
+    let sides: Vec<Arc<dyn Hit>> = vec![
+        Arc::new(XyRect::new(0.0..size.x, 0.0..size.y, 0.0, material.clone())),
+        Arc::new(XyRect::new(0.0..size.x, 0.0..size.y, size.z, material.clone())),
+        Arc::new(XzRect::new(0.0..size.x, 0.0..size.z, 0.0, material.clone())),
+        Arc::new(XzRect::new(0.0..size.x, 0.0..size.z, size.y, material.clone())),
+        Arc::new(YzRect::new(0.0..size.y, 0.0..size.z, 0.0, material.clone())),
+        Arc::new(YzRect::new(0.0..size.y, 0.0..size.z, size.x, material)),
+    ];
+
+    let placement = Transform::rotation(Vec3::new(0.0, 1.0, 0.0), degrees).then(&Transform::translation(offset));
+    Instance::new(Arc::new(BvhNode::new(sides)), placement)
+}
+
+/// Just a gray ground under the sky, seen from the random scene's camera. A blank
+/// canvas for scenes put together with `inline::add` (--add on the command line).
+pub fn empty(aspect_ratio: f64) -> Scene {
+    let mut world = HittableList::default();
+    let ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add_named("/ground", Sphere::new(0.0, -1000.0, 0.0, 1000.0, ground));
+
+    let lookfrom = Point3::new(13.0, 2.0, 3.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(lookfrom, lookat, vup, 20.0, aspect_ratio, 0.0, 10.0);
+
+    // Paths leave for the sky after a bounce or two, none get long
+    let limits = PathLimits { max_bounces: 16, ..PathLimits::DEFAULT };
+
+    Scene { world, camera, limits }
+}
@@ -1,15 +1,17 @@
-use std::ops::Range;
 use std::sync::Arc;
 
+use crate::aabb::Aabb;
 use crate::hit::Hit;
+use crate::interval::Interval;
 use crate::material::*;
-use crate::vec3::Point3;
+use crate::vec3::{Point3, Vec3};
 
 #[derive(Clone)]
 pub struct Sphere{
     center: Point3,
     radius: f64,
-    material: Arc<dyn Material>
+    material: Arc<dyn Material>,
+    reproject: bool
 }
 
 impl Sphere {
@@ -17,44 +19,138 @@ impl Sphere {
         Self {
             center: Point3::new(x, y, z),
             radius,
-            material
+            material,
+            reproject: true
         }
     }
+
+    /// Whether hit points are snapped back onto the sphere's surface (on by default).
+    /// `ray.at(t)` can land slightly off the surface on huge spheres, which shows
+    /// up as acne since bounced rays start on the wrong side of it.
+    pub fn with_reprojection(mut self, reproject: bool) -> Self {
+        self.reproject = reproject;
+        self
+    }
+
+    /// Spherical mapping of a point p on the unit sphere (centered at the origin)
+    /// u: angle around the Y axis from X = -1, v: angle from Y = -1 to Y = +1
+    pub fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + std::f64::consts::PI;
+
+        (phi / (2.0 * std::f64::consts::PI), theta / std::f64::consts::PI)
+    }
 }
 
-impl Hit for Sphere {
-    fn try_hit(&self, ray: &Ray, interval: &Range<f64>) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.len_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.len_squared() - (self.radius * self.radius);
-
-        let discriminant = (half_b * half_b) - (a * c);
-        if discriminant < 0.0 {
-            return None;
+/// Hits where the sphere's radius and the distance the ray travelled differ in
+/// scale by more than this factor get their root refined (see `Sphere::refine`)
+const REFINE_SCALE_RATIO: f64 = 16.0;
+
+/// Solve the ray/sphere quadratic, returning the closest root inside the interval.
+pub fn intersect(center: &Point3, radius: f64, ray: &Ray, interval: &Interval) -> Option<f64> {
+    let (t0, t1) = roots(center, radius, ray)?;
+
+    // Try to get both the near and far roots
+    match (interval.surrounds(t0), interval.surrounds(t1)) {
+        (true, _) => Some(t0),
+        (false, true) => Some(t1),
+        (false, false) => None,
+    }
+}
+
+/// Both roots of the ray/sphere quadratic in increasing order, if the ray's line
+/// hits the sphere at all. Uses the numerically stable formulation, so hits on huge
+/// spheres (like the radius 1000 ground) or from far away don't suffer from
+/// catastrophic cancellation.
+fn roots(center: &Point3, radius: f64, ray: &Ray) -> Option<(f64, f64)> {
+    let oc = ray.origin - center;
+    let a = ray.direction.len_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.len_squared() - (radius * radius);
+
+    // b² - ac subtracts two huge, nearly equal numbers when the sphere is big.
+    // Instead use the squared distance from the center to the ray's line, which
+    // is the same discriminant (scaled by a) without the cancellation.
+    let perpendicular = oc - (half_b / a) * ray.direction;
+    let discriminant = a * ((radius * radius) - perpendicular.len_squared());
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Kahan's trick: get the root with the larger magnitude without any subtraction
+    // of similar values, then the other one through t0 * t1 = c / a (Vieta)
+    let q = -(half_b + half_b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return Some((0.0, 0.0));
+    }
+
+    let (t0, t1) = (q / a, c / q);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+impl Sphere {
+    /// Adaptive precision for huge spheres (or tiny ones seen from far away): the
+    /// root's error grows with the scale of the numbers in the quadratic, and
+    /// near the horizon of a huge sphere that error is enough to land the hit
+    /// point on the wrong side of the surface (the classic banding/acne).
+    /// Solving again from the first estimate only has to find a tiny correction,
+    /// whose error is tiny as well.
+    fn refine(&self, ray: &Ray, root: f64, interval: &Interval) -> f64 {
+        // Negative radii (hollow spheres, normals facing in) are just as big
+        let (distance, radius) = (root * ray.direction.len(), self.radius.abs());
+        let mismatched = radius > REFINE_SCALE_RATIO * distance
+            || distance > REFINE_SCALE_RATIO * radius;
+        if !mismatched {
+            return root;
+        }
+
+        let restarted = Ray::new(ray.at(root), ray.direction);
+        let correction = match roots(&self.center, self.radius, &restarted) {
+            // The correction is whichever root is closest to the estimate
+            Some((t0, t1)) => match t0.abs() < t1.abs() {
+                true => t0,
+                false => t1,
+            },
+            None => return root,
+        };
+
+        match interval.surrounds(root + correction) {
+            true => root + correction,
+            false => root,
         }
+    }
+}
 
-        let discriminant_sqrt = discriminant.sqrt();
+impl Hit for Sphere {
+    fn try_hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+        let root = intersect(&self.center, self.radius, ray, interval)?;
+        let root = self.refine(ray, root, interval);
 
-        // Try to get both the +Δ and -Δ roots
-        let mut root = (-half_b - discriminant_sqrt) / a;
-        if !interval.contains(&root) {
-            root = (-half_b + discriminant_sqrt) / a;
-            if !interval.contains(&root) {
-                return None;
-            }
+        let mut hit_point = ray.at(root);
+        if self.reproject {
+            hit_point = self.center + self.radius.abs() * (hit_point - self.center).unit_vec();
         }
 
-        let hit_point = ray.at(root);
         let outward_normal = (hit_point - self.center) / self.radius;
         let hit = HitRecord::new(
             root,
             hit_point,
             outward_normal,
-            &ray,
+            Sphere::uv(&outward_normal),
+            ray,
             Arc::clone(&self.material)
         );
-        
+
+        // u is the angle around the Y axis, so it grows along (z, 0, -x)
+        let hit = hit.with_tangent(Vec3::new(outward_normal.z, 0.0, -outward_normal.x));
+
         Some(hit)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        // A negative radius only flips the normals, the sphere is as big either way
+        let radius = self.radius.abs();
+        let radius = Vec3::new(radius, radius, radius);
+        Aabb::new(self.center - radius, self.center + radius)
+    }
 }
@@ -0,0 +1,60 @@
+//! Traversal statistics for diagnosing slow scenes and tuning the BVH builder.
+//!
+//! Counting is only compiled in with the `stats` cargo feature, without it
+//! every function here is a no-op and `take` always reports zeros.
+
+#[cfg(feature = "stats")]
+use std::cell::Cell;
+
+/// Work done by the acceleration structure since the last `take`
+#[derive(Clone, Copy, Default)]
+pub struct TraversalStats {
+    pub nodes_visited: u64,
+    pub primitives_tested: u64
+}
+
+impl TraversalStats {
+    /// Total amount of tests (box and primitive) performed
+    pub fn total(&self) -> u64 {
+        self.nodes_visited + self.primitives_tested
+    }
+}
+
+#[cfg(feature = "stats")]
+thread_local! {
+    // Counters are per thread, so rendering a pixel on one thread and taking
+    // the stats right after gives that pixel's numbers
+    static STATS: Cell<TraversalStats> = Cell::new(TraversalStats::default());
+}
+
+#[inline(always)]
+pub fn count_node() {
+    #[cfg(feature = "stats")]
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        current.nodes_visited += 1;
+        stats.set(current);
+    });
+}
+
+#[inline(always)]
+pub fn count_primitive() {
+    #[cfg(feature = "stats")]
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        current.primitives_tested += 1;
+        stats.set(current);
+    });
+}
+
+/// Return this thread's counters and reset them
+pub fn take() -> TraversalStats {
+    #[cfg(feature = "stats")]
+    return STATS.with(|stats| stats.take());
+
+    #[cfg(not(feature = "stats"))]
+    TraversalStats::default()
+}
+
+/// Whether the counters are compiled in at all
+pub const ENABLED: bool = cfg!(feature = "stats");
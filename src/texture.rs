@@ -0,0 +1,323 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image::RgbImage;
+
+use crate::color::Color;
+use crate::film::Film;
+use crate::perlin::Perlin;
+use crate::vec3::{Point3, Vec3};
+use crate::worley::Worley;
+
+/// Color that varies over a surface, looked up by the hit's surface coordinates
+/// (u, v) and/or its position p
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+/// The same color everywhere
+pub struct SolidColor {
+    color: Color
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// 3D checkerboard: space is split into cubes of side `scale`, alternating between
+/// the two textures. Being solid it needs no uv mapping and doesn't stretch on
+/// spheres, like the book's checkered ground.
+pub struct Checker {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self { inv_scale: 1.0 / scale, even, odd }
+    }
+
+    /// Checkers of two plain colors
+    pub fn solid(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = |coord: f64| (coord * self.inv_scale).floor() as i64;
+        match (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 {
+            true => self.even.value(u, v, p),
+            false => self.odd.value(u, v, p),
+        }
+    }
+}
+
+/// Perlin noise shading `color` from black to full, for marble, clouds and other
+/// procedural variation. Features are about 1 / `frequency` units across.
+pub struct NoiseTexture {
+    noise: Perlin,
+    frequency: f64,
+    color: Color
+}
+
+impl NoiseTexture {
+    pub fn new(frequency: f64) -> Self {
+        Self { noise: Perlin::new(0), frequency, color: crate::color::WHITE }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Another pattern of the same noise
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new(seed);
+        self
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        self.color * (0.5 * (1.0 + self.noise.noise(&(*p * self.frequency))))
+    }
+}
+
+/// Marble: stripes along z, sin of the position plus turbulence bending them into
+/// veins. `frequency` sets how close the stripes are.
+pub struct Marble {
+    noise: Perlin,
+    frequency: f64,
+    color: Color
+}
+
+impl Marble {
+    pub fn new(frequency: f64) -> Self {
+        Self { noise: Perlin::new(0), frequency, color: crate::color::WHITE }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Perlin::new(seed);
+        self
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        // 7 octaves is where finer turbulence stops showing
+        let phase = self.frequency * p.z + 10.0 * self.noise.turbulence(p, 7);
+        self.color * (0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// What a cellular texture shades by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellPattern {
+    /// Distance to the closest cell center, dark there and bright at the edges:
+    /// scales, bubbles
+    Cells,
+    /// Thin dark lines along the edges between cells: cracked earth, dry mud
+    Cracks
+}
+
+/// Worley noise shading `color` from black to full, cells about 1 / `frequency`
+/// units across
+pub struct Cellular {
+    noise: Worley,
+    frequency: f64,
+    color: Color,
+    pattern: CellPattern
+}
+
+impl Cellular {
+    pub fn new(pattern: CellPattern, frequency: f64) -> Self {
+        Self { noise: Worley::new(0), frequency, color: crate::color::WHITE, pattern }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Worley::new(seed);
+        self
+    }
+}
+
+impl Texture for Cellular {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let (first, second) = self.noise.distances(&(*p * self.frequency));
+        let shade = match self.pattern {
+            CellPattern::Cells => first,
+            // Cracks a tenth of a cell wide, fading into the cell
+            CellPattern::Cracks => (second - first) / 0.1,
+        };
+        self.color * shade.clamp(0.0, 1.0)
+    }
+}
+
+/// How image lookups between texel centers are filled in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// The closest texel, blocky up close: for pixel art
+    Nearest,
+    /// Blend of the 4 closest texels, the default
+    Bilinear
+}
+
+/// Image wrapped around the surface by its (u, v) coordinates, u going right and
+/// v up the image: earth mapped spheres, textured meshes, ...
+pub struct ImageTexture {
+    /// Linear colors, like everything the integrator deals with
+    texels: Film,
+    filter: Filter
+}
+
+impl ImageTexture {
+    pub fn new(img: &RgbImage) -> Self {
+        Self { texels: Film::from_image(img), filter: Filter::Bilinear }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Load a PNG, JPEG or any other format the image crate reads
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let img = image::open(path).map_err(|e| format!("can't load '{}': {}", path.display(), e))?;
+        Ok(Self::new(&img.to_rgb8()))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let (width, height) = (self.texels.width(), self.texels.height());
+        if width == 0 || height == 0 {
+            return crate::color::MAGENTA;    // Loud, rather than nothing to sample
+        }
+
+        // Rows go down the image while v goes up
+        let x = u.clamp(0.0, 1.0) * width as f64;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * height as f64;
+        match self.filter {
+            Filter::Nearest => self.texels.pixel((x as usize).min(width - 1), (y as usize).min(height - 1)),
+            Filter::Bilinear => self.texels.sample(x, y),
+        }
+    }
+}
+
+/// Another texture moved around the surface: its (u, v) are turned by `rotation`
+/// around the middle, repeated `scale` times across and shifted by `offset` tiles,
+/// then wrapped so it tiles. Places image textures without editing the images.
+pub struct UvTransform {
+    texture: Arc<dyn Texture>,
+    scale: (f64, f64),
+    offset: (f64, f64),
+    /// In radians
+    rotation: f64
+}
+
+impl UvTransform {
+    pub fn new(texture: Arc<dyn Texture>) -> Self {
+        Self { texture, scale: (1.0, 1.0), offset: (0.0, 0.0), rotation: 0.0 }
+    }
+
+    /// Tiles across u and v
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = (u, v);
+        self
+    }
+
+    pub fn with_offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (du, dv) = (u - 0.5, v - 0.5);
+        let (u, v) = (cos * du - sin * dv + 0.5, sin * du + cos * dv + 0.5);
+
+        let u = (u * self.scale.0 + self.offset.0).rem_euclid(1.0);
+        let v = (v * self.scale.1 + self.offset.1).rem_euclid(1.0);
+        self.texture.value(u, v, p)
+    }
+}
+
+/// What a ramp reads its position from
+#[derive(Clone, Copy)]
+pub enum RampInput {
+    U,
+    V,
+    /// Position along the axis (p · axis), so its length scales the ramp
+    Axis(Vec3)
+}
+
+/// Color gradient through any number of stops, driven by a surface coordinate or
+/// the position along an axis: stylized skies (as a background, where the axis is
+/// dotted with the ray's direction), height based tints, quick variation...
+pub struct Ramp {
+    input: RampInput,
+    /// Sorted by position, colors in between are blended linearly
+    stops: Vec<(f64, Color)>
+}
+
+impl Ramp {
+    /// Stops are (position, color), in any order. Before the first one and after
+    /// the last the ramp keeps their color.
+    pub fn new(input: RampInput, mut stops: Vec<(f64, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { input, stops }
+    }
+
+    pub fn input(&self) -> RampInput {
+        self.input
+    }
+
+    /// Color at position `t`
+    pub fn at(&self, t: f64) -> Color {
+        let after = self.stops.partition_point(|&(position, _)| position <= t);
+        match (self.stops.get(after.wrapping_sub(1)), self.stops.get(after)) {
+            (Some(&(start, from)), Some(&(end, to))) => {
+                let blend = (t - start) / (end - start);
+                (1.0 - blend) * from + blend * to
+            },
+            (Some(&(_, color)), None) | (None, Some(&(_, color))) => color,
+            (None, None) => crate::color::BLACK,
+        }
+    }
+}
+
+impl Texture for Ramp {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.at(match self.input {
+            RampInput::U => u,
+            RampInput::V => v,
+            RampInput::Axis(axis) => p.dot(&axis),
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use crate::vec3::{Point3, Vec3};
+
+/// 3x3 row-major matrix
+type Mat3 = [[f64; 3]; 3];
+
+const IDENTITY: Mat3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Affine transform: a linear part (rotation, scale, shear) followed by a
+/// translation. The inverse is kept around since rays are transformed into
+/// object space far more often than transforms are built.
+#[derive(Clone, Copy)]
+pub struct Transform {
+    linear: Mat3,
+    inverse: Mat3,
+    translation: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self { linear: IDENTITY, inverse: IDENTITY, translation: Vec3::default() }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self { translation: offset, ..Self::identity() }
+    }
+
+    /// Non-uniform scale along each axis, no factor may be zero
+    pub fn scale(factors: Vec3) -> Self {
+        let linear = [[factors.x, 0.0, 0.0], [0.0, factors.y, 0.0], [0.0, 0.0, factors.z]];
+        Self::from_linear(linear)
+    }
+
+    /// Rotation of `degrees` around `axis` (right-hand rule), Rodrigues' formula
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let Vec3 { x, y, z } = axis.unit_vec();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+
+        let linear = [
+            [cos + x * x * k, x * y * k - z * sin, x * z * k + y * sin],
+            [y * x * k + z * sin, cos + y * y * k, y * z * k - x * sin],
+            [z * x * k - y * sin, z * y * k + x * sin, cos + z * z * k],
+        ];
+        Self::from_linear(linear)
+    }
+
+    /// Transform with the given linear part and no translation. Panics if the
+    /// matrix isn't invertible.
+    pub fn from_linear(linear: Mat3) -> Self {
+        let inverse = invert(&linear).expect("Transform matrix must be invertible");
+        Self { linear, inverse, translation: Vec3::default() }
+    }
+
+    /// Transform that applies `self` first and then `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            linear: mul(&next.linear, &self.linear),
+            inverse: mul(&self.inverse, &next.inverse),
+            translation: apply(&next.linear, &self.translation) + next.translation
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        apply(&self.linear, p) + self.translation
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        apply(&self.linear, v)
+    }
+
+    /// Normals transform by the inverse transpose, so they stay perpendicular
+    /// to the surface under non-uniform scaling. The result isn't normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        apply(&transpose(&self.inverse), n)
+    }
+
+    pub fn inverse_point(&self, p: &Point3) -> Point3 {
+        apply(&self.inverse, &(*p - self.translation))
+    }
+
+    pub fn inverse_vector(&self, v: &Vec3) -> Vec3 {
+        apply(&self.inverse, v)
+    }
+}
+
+fn apply(m: &Mat3, v: &Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    out
+}
+
+fn transpose(m: &Mat3) -> Mat3 {
+    [
+        [m[0][0], m[1][0], m[2][0]],
+        [m[0][1], m[1][1], m[2][1]],
+        [m[0][2], m[1][2], m[2][2]],
+    ]
+}
+
+/// Inverse through the adjugate (transposed cofactors) over the determinant
+fn invert(m: &Mat3) -> Option<Mat3> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+
+    let det = m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    Some(adjugate.map(|row| row.map(|value| value / det)))
+}
@@ -1,6 +1,7 @@
 use std::ops::{
     Add, AddAssign,
     Div, DivAssign,
+    Index,
     Mul, MulAssign,
     Neg, Range,
     Sub
@@ -8,7 +9,7 @@ use std::ops::{
 
 pub type Point3 = Vec3;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -63,13 +64,30 @@ impl Vec3 {
         }
     }
 
+    /// Point of the unit circle for a 2D sample in [0, 1)², by Shirley and Chiu's
+    /// concentric mapping: no rejection loop, and evenly spread samples stay evenly
+    /// spread on the disk
+    pub fn concentric_in_unit_disk((u1, u2): (f64, f64)) -> Vec3 {
+        let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
+        }
+
+        // Squares around the center map to circles, split in 4 wedges
+        let (r, theta) = match a.abs() > b.abs() {
+            true => (a, std::f64::consts::FRAC_PI_4 * (b / a)),
+            false => (b, std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (a / b)),
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
     pub fn rand_unit_vec() -> Vec3 {
         Self::rand_in_unit_sphere().unit_vec()
     }
 
     pub fn rand_in_hemisphere(normal: &Vec3) -> Vec3 {
         let in_unit_sphere = Vec3::rand_in_unit_sphere();
-        if in_unit_sphere.dot(&normal) > 0.0 {
+        if in_unit_sphere.dot(normal) > 0.0 {
             in_unit_sphere
         } else {
             -in_unit_sphere
@@ -103,11 +121,11 @@ impl Vec3 {
     }
 
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
-        *self - self.dot(&normal) * normal * 2.0
+        *self - self.dot(normal) * normal * 2.0
     }
 
     pub fn refract(&self, normal: &Vec3, eta_ratio: f64) -> Vec3 {
-        let cos_theta = -self.dot(&normal).min(1.0);
+        let cos_theta = -self.dot(normal).min(1.0);
         let ray_perpendicular = eta_ratio * (*self + cos_theta * normal);
         let ray_parallel = -(1.0 - ray_perpendicular.len_squared()).abs().sqrt() * normal;
 
@@ -118,6 +136,30 @@ impl Vec3 {
         let almost_zero = 1e-8;
         [self.x, self.y, self.z].iter().any(|&val| val.abs() < almost_zero)
     }
+
+    /// Component-wise minimum of two vectors
+    pub fn min(&self, other: &Vec3) -> Vec3 {
+        Vec3::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    /// Component-wise maximum of two vectors
+    pub fn max(&self, other: &Vec3) -> Vec3 {
+        Vec3::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+}
+
+// Index a Vec3 by axis: 0 -> x, 1 -> y, 2 -> z
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of bounds: {}", axis),
+        }
+    }
 }
 
 // Impls of Vec3 unary operators
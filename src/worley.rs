@@ -0,0 +1,54 @@
+use crate::vec3::{Point3, Vec3};
+
+/// Worley (cellular) noise: a random feature point in every cell of an integer
+/// lattice, valued by the distances to the closest ones. Where Perlin noise is
+/// smooth, this is made of cells: cracked earth, scales, stone tiles, ...
+pub struct Worley {
+    seed: u64
+}
+
+impl Worley {
+    /// The same seed always gives the same cells, no matter the render's seed
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Distances from `p` to the closest and the second closest feature points
+    /// (F1 and F2). F1 is 0 on a point and rarely above 1, F2 - F1 is 0 on the
+    /// edges between cells.
+    pub fn distances(&self, p: &Point3) -> (f64, f64) {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+
+        // A cell's point is inside it, so the closest two are in the 27 cells around
+        let (mut first, mut second) = (f64::INFINITY, f64::INFINITY);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let cell = (i + di, j + dj, k + dk);
+                    let corner = Vec3::new(cell.0 as f64, cell.1 as f64, cell.2 as f64);
+                    let distance = (corner + self.feature(cell) - *p).len();
+                    if distance < first {
+                        (first, second) = (distance, first);
+                    } else if distance < second {
+                        second = distance;
+                    }
+                }
+            }
+        }
+
+        (first, second)
+    }
+
+    /// Where the cell's feature point is, relative to its lowest corner
+    fn feature(&self, (i, j, k): (i64, i64, i64)) -> Vec3 {
+        // splitmix64 finalizer - neighbouring cells get unrelated points
+        let mut z = self.seed ^ (i as u64).wrapping_mul(0x9e3779b97f4a7c15)
+            ^ (j as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+            ^ (k as u64).wrapping_mul(0x165667b19e3779f9);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        let rng = fastrand::Rng::with_seed(z ^ (z >> 31));
+        Vec3::new(rng.f64(), rng.f64(), rng.f64())
+    }
+}